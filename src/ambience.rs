@@ -0,0 +1,120 @@
+extern crate songbird;
+extern crate tokio;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use songbird::input::Restartable;
+use songbird::tracks::{create_player, TrackHandle};
+use songbird::Call;
+use tokio::sync::Mutex;
+
+use crate::session::Session;
+use crate::settings::{AmbienceSettings, WeatherSettings};
+use crate::weather::Weather;
+
+/// An ambient loop playing under a guild's song.
+pub struct AmbientTrack {
+    /// Weather the loop was made for.
+    pub weather: Weather,
+    /// Volume before the guild's volume is applied.
+    pub volume: f32,
+    pub track: TrackHandle,
+}
+
+/// Ambient loops, such as rain or wind, played under the music in their weather.
+pub struct Ambience {
+    sounds: HashMap<Weather, PathBuf>,
+    /// Volume in the heaviest weather.
+    volume: f32,
+}
+
+impl Ambience {
+    /// Finds the loops in the ambience folder, each named after its weather, e.g. rainy.ogg.
+    /// Without a folder, or if it cannot be read, there is no ambience.
+    pub fn load(settings: &AmbienceSettings) -> Self {
+        let mut sounds = HashMap::new();
+        if let Some(path) = &settings.path {
+            match fs::read_dir(path) {
+                Ok(entries) => {
+                    for entry in entries.flatten() {
+                        let file = entry.path();
+                        let stem = file.file_stem().map(|stem| stem.to_string_lossy());
+                        match stem.as_deref().map(str::parse::<Weather>) {
+                            Some(Ok(weather)) if file.is_file() => {
+                                sounds.insert(weather, file);
+                            }
+                            _ => {}
+                        }
+                    }
+                    println!("{} ambient loops found.", sounds.len());
+                }
+                Err(e) => println!("No ambience: could not read {}: {}", path, e),
+            }
+        }
+
+        Ambience {
+            sounds,
+            volume: settings.volume,
+        }
+    }
+
+    /// The loop for `weather`, or for the first of its fallbacks that has one.
+    fn sound(&self, weather: Weather, settings: &WeatherSettings) -> Option<(Weather, &Path)> {
+        std::iter::once(weather)
+            .chain(settings.fallback_chain(weather))
+            .find_map(|weather| Some((weather, self.sounds.get(&weather)?.as_path())))
+    }
+
+    /// Plays the loop for `weather` under the session's song, louder the higher its
+    /// `intensity` is, or stops the ambience if the weather has none. A loop that is
+    /// already playing only has its volume changed. The call is only locked once the new
+    /// loop is ready to play.
+    pub async fn update(
+        &self,
+        call_lock: &Mutex<Call>,
+        session: &mut Session,
+        weather: Weather,
+        intensity: f32,
+        settings: &WeatherSettings,
+        guild_volume: f32,
+    ) {
+        let sound = self.sound(weather, settings);
+        let volume = self.volume * intensity.clamp(0.0, 1.0);
+
+        if let (Some(playing), Some((weather, _))) = (&mut session.ambience, sound) {
+            if playing.weather == weather {
+                playing.volume = volume;
+                let _ = playing.track.set_volume(volume * guild_volume);
+                return;
+            }
+        }
+
+        if let Some(old) = session.ambience.take() {
+            let _ = old.track.stop();
+        }
+        let (weather, path) = match sound {
+            Some(sound) => sound,
+            None => return,
+        };
+        // Restartable sources can seek back to the start, which looping needs.
+        let source = match Restartable::ffmpeg(path.to_path_buf(), false).await {
+            Ok(source) => source,
+            Err(e) => {
+                println!("Could not play the ambience {}: {:?}", path.display(), e);
+                return;
+            }
+        };
+
+        let (mut track, handle) = create_player(source.into());
+        track.set_volume(volume * guild_volume);
+        call_lock.lock().await.play(track);
+        let _ = handle.enable_loop();
+        session.ambience = Some(AmbientTrack {
+            weather,
+            volume,
+            track: handle,
+        });
+    }
+}
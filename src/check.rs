@@ -0,0 +1,161 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::library::{scan, SongKey, REQUIRED_VARIANTS};
+use crate::season::SEASONS;
+use crate::weather::{Weather, WEATHERS};
+
+/// Validates the songs folder at `path` and prints a report: a coverage matrix of variant
+/// by hour, files that are not valid song names, missing keys of the required variants,
+/// duplicate keys and files ffmpeg cannot decode. Returns `false` if any problem was found.
+/// Missing rainy and snowy keys are only warned about, since they fall back to normal.
+pub fn check_library(path: &Path) -> bool {
+    let scan = match scan(path) {
+        Ok(scan) => scan,
+        Err(e) => {
+            println!("{}", e);
+            return false;
+        }
+    };
+
+    println!("Checking songs folder {}\n", path.display());
+
+    // Variant rows by hour columns. '#' has a song, '.' has none, '!' has duplicates.
+    // Seasonal arrangements get their own rows, for the seasons the library has any of.
+    let mut seasons = vec![None];
+    seasons.extend(
+        SEASONS
+            .iter()
+            .filter(|season| scan.songs.keys().any(|key| key.season == Some(**season)))
+            .map(|season| Some(*season)),
+    );
+    print!("{:>19}", "");
+    for hour in 0..24 {
+        print!(" {:02}", hour);
+    }
+    println!();
+    for season in seasons {
+        for weather in WEATHERS
+            .iter()
+            .filter(|weather| **weather != Weather::Unknown)
+        {
+            let mut key = SongKey::new(*weather, 0);
+            key.season = season;
+            match season {
+                Some(season) => print!("{:>19}", format!("{} {}", weather.name(), season)),
+                None => print!("{:>19}", weather.name()),
+            }
+            for hour in 0..24 {
+                let mark = match scan.songs.get(&SongKey { hour, ..key }).map(Vec::len) {
+                    None => '.',
+                    Some(1) => '#',
+                    Some(_) => '!',
+                };
+                print!("  {}", mark);
+            }
+            println!();
+        }
+    }
+    println!();
+
+    for e in &scan.manifest_errors {
+        println!("Invalid manifest entry {}", e);
+    }
+    for (file, e) in &scan.invalid {
+        println!("Invalid song name {}: {}", file.display(), e);
+    }
+
+    // Only the normal variant is needed, the others fall back to it.
+    let (missing, fallback): (Vec<SongKey>, Vec<SongKey>) = REQUIRED_VARIANTS
+        .iter()
+        .flat_map(|weather| (0..24).map(move |hour| SongKey::new(*weather, hour)))
+        .filter(|key| !scan.songs.contains_key(key))
+        .partition(|key| key.variant == Weather::Clear);
+    if !missing.is_empty() {
+        println!("Missing {} keys: {}", missing.len(), join_keys(&missing));
+    }
+    if !fallback.is_empty() {
+        println!(
+            "Warning: {} keys fall back to another variant: {}",
+            fallback.len(),
+            join_keys(&fallback)
+        );
+    }
+
+    let mut duplicates: Vec<_> = scan
+        .songs
+        .iter()
+        .filter(|(_, files)| files.len() > 1)
+        .collect();
+    duplicates.sort_by_key(|(key, _)| **key);
+    for (key, files) in &duplicates {
+        println!("Duplicate files for {}:", key);
+        for song in files.iter() {
+            println!("    {}", song.path.display());
+        }
+    }
+
+    let mut files: Vec<_> = scan
+        .songs
+        .values()
+        .flatten()
+        .map(|song| &song.path)
+        .collect();
+    files.sort();
+    let mut undecodable = 0;
+    for file in files {
+        if let Err(e) = decode(file) {
+            println!("Cannot decode {}: {}", file.display(), e);
+            undecodable += 1;
+        }
+    }
+
+    let ok = scan.manifest_errors.is_empty()
+        && scan.invalid.is_empty()
+        && missing.is_empty()
+        && duplicates.is_empty()
+        && undecodable == 0;
+    if ok {
+        println!("No problems found.");
+    } else {
+        println!(
+            "\n{} invalid, {} missing, {} duplicated, {} undecodable.",
+            scan.manifest_errors.len() + scan.invalid.len(),
+            missing.len(),
+            duplicates.len(),
+            undecodable
+        );
+    }
+    ok
+}
+
+/// The keys as a comma separated list.
+fn join_keys(keys: &[SongKey]) -> String {
+    keys.iter()
+        .map(|key| key.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Runs the file through ffmpeg without writing any output.
+fn decode(file: &Path) -> Result<(), String> {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-i"])
+        .arg(file)
+        .args(["-f", "null", "-"])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("could not run ffmpeg: {}", e))?;
+
+    let errors = String::from_utf8_lossy(&output.stderr);
+    if output.status.success() && errors.trim().is_empty() {
+        Ok(())
+    } else {
+        Err(errors
+            .trim()
+            .lines()
+            .next()
+            .unwrap_or("ffmpeg failed")
+            .to_string())
+    }
+}
@@ -0,0 +1,112 @@
+extern crate chrono_tz;
+extern crate serde;
+extern crate serde_json;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::timezone::zone_at;
+use crate::weather::Location;
+
+/// Settings a guild has changed with commands. Anything left as `None` uses the bot settings.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuildSettings {
+    pub location: Option<Location>,
+    /// Name of the song pack the guild picked.
+    pub pack: Option<String>,
+    /// Whether the pack's chime plays at the top of the hour, on if not set.
+    pub chime: Option<bool>,
+    /// Playback volume in percent, 100 if not set.
+    pub volume: Option<u32>,
+    /// Name of the time zone the guild set, e.g. "Europe/Paris". The zone at the guild's
+    /// location if not set.
+    pub timezone: Option<String>,
+}
+
+/// Per-guild settings, saved as JSON so they survive restarts.
+pub struct GuildStore {
+    path: PathBuf,
+    guilds: HashMap<u64, GuildSettings>,
+}
+
+impl GuildStore {
+    /// Reads the guild settings at `path`, starting empty if the file does not exist yet.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let guilds = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| format!("Invalid {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(format!("Could not read {}: {}", path.display(), e)),
+        };
+
+        Ok(GuildStore {
+            path: path.to_path_buf(),
+            guilds,
+        })
+    }
+
+    pub fn get(&self, guild_id: u64) -> GuildSettings {
+        self.guilds.get(&guild_id).cloned().unwrap_or_default()
+    }
+
+    /// The guild's location, or `default` if it never set one.
+    pub fn location(&self, guild_id: u64, default: &Location) -> Location {
+        self.guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.location)
+            .unwrap_or(*default)
+    }
+
+    /// The pack the guild picked, `None` to use the default pack.
+    pub fn pack(&self, guild_id: u64) -> Option<String> {
+        self.guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.pack.clone())
+    }
+
+    /// Whether the guild hears the chime at the top of the hour.
+    pub fn chime(&self, guild_id: u64) -> bool {
+        self.guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.chime)
+            .unwrap_or(true)
+    }
+
+    /// The time zone the guild set, or else the zone at its location.
+    pub fn timezone(&self, guild_id: u64, default_location: &Location) -> Tz {
+        self.guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.timezone.as_deref()?.parse().ok())
+            .unwrap_or_else(|| zone_at(&self.location(guild_id, default_location)))
+    }
+
+    /// The guild's playback volume as a track volume, 1.0 being unchanged.
+    pub fn volume(&self, guild_id: u64) -> f32 {
+        let percent = self
+            .guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.volume)
+            .unwrap_or(100);
+        percent as f32 / 100.0
+    }
+
+    /// Changes a guild's settings and writes the store back to disk.
+    pub fn update<F>(&mut self, guild_id: u64, change: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut GuildSettings),
+    {
+        change(self.guilds.entry(guild_id).or_default());
+        self.save()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let contents = serde_json::to_string_pretty(&self.guilds)?;
+        fs::write(&self.path, contents)
+    }
+}
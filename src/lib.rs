@@ -1 +1,11 @@
+pub mod ambience;
+pub mod check;
+pub mod guilds;
+pub mod library;
+pub mod looping;
+pub mod season;
+pub mod session;
+pub mod settings;
+pub mod timezone;
+pub mod transition;
 pub mod weather;
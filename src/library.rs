@@ -0,0 +1,697 @@
+extern crate notify;
+extern crate serde;
+extern crate serde_json;
+extern crate tokio;
+extern crate toml;
+
+mod loop_tags;
+mod loudness;
+mod manifest;
+mod packs;
+mod watch;
+
+pub use loop_tags::read_loop_tags;
+pub use loudness::{Loudness, LoudnessCache};
+pub use manifest::{Manifest, ManifestEntry, MANIFEST_FILE};
+pub use packs::{pack_dirs, Pack, Packs};
+pub use watch::SongWatcher;
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::season::Season;
+use crate::weather::Weather;
+
+/// The variants a full library has a song for at every hour.
+pub const REQUIRED_VARIANTS: [Weather; 3] = [Weather::Clear, Weather::Rainy, Weather::Snowy];
+
+/// The slot a song plays in: a weather variant, an hour of the day and, for songs with a
+/// seasonal arrangement, a season.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SongKey {
+    pub variant: Weather,
+    pub hour: u32,
+    /// `None` for songs played in every season.
+    pub season: Option<Season>,
+}
+
+/// Why a file name is not a valid `VXX_` song name, see songs/README.txt.
+#[derive(Debug, PartialEq)]
+pub enum SongKeyError {
+    /// Fewer than the 3 characters of the key before the extension or a separator.
+    TooShort,
+    /// The first character is not a variant digit.
+    UnknownVariant(char),
+    /// The 2nd and 3rd characters are not an hour from 00 to 23.
+    InvalidHour(String),
+    /// The name after `-` is not a season.
+    UnknownSeason(String),
+    /// The key is not followed by `_`, the extension or the end of the name.
+    MissingSeparator,
+}
+
+impl fmt::Display for SongKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SongKeyError::TooShort => write!(f, "name is shorter than the 3 character key"),
+            SongKeyError::UnknownVariant(c) => write!(f, "unknown variant '{}'", c),
+            SongKeyError::InvalidHour(hour) => {
+                write!(f, "\"{}\" is not an hour from 00 to 23", hour)
+            }
+            SongKeyError::UnknownSeason(season) => write!(f, "unknown season \"{}\"", season),
+            SongKeyError::MissingSeparator => write!(f, "key must be followed by '_'"),
+        }
+    }
+}
+
+impl Error for SongKeyError {}
+
+impl SongKey {
+    /// The key of a song played in every season.
+    pub fn new(variant: Weather, hour: u32) -> Self {
+        SongKey {
+            variant,
+            hour,
+            season: None,
+        }
+    }
+
+    /// The same slot, but only in `season`.
+    pub fn in_season(self, season: Season) -> Self {
+        SongKey {
+            season: Some(season),
+            ..self
+        }
+    }
+
+    /// Parses the key at the start of a song file name, e.g. "117_5PM-Rainy.ogg",
+    /// or "117-summer_5PM-Rainy.ogg" for a seasonal arrangement.
+    pub fn from_file_name(name: &str) -> Result<Self, SongKeyError> {
+        let is_separator = |c: &char| matches!(c, '_' | '-' | '.');
+        let mut chars = name.chars();
+        let (variant, tens, ones) = match (chars.next(), chars.next(), chars.next()) {
+            (Some(variant), Some(tens), Some(ones))
+                if ![variant, tens, ones].iter().any(is_separator) =>
+            {
+                (variant, tens, ones)
+            }
+            _ => return Err(SongKeyError::TooShort),
+        };
+
+        let season = match chars.next() {
+            None | Some('_') | Some('.') => None,
+            Some('-') => {
+                let season: String = chars.take_while(|c| *c != '_' && *c != '.').collect();
+                let parsed = season
+                    .parse()
+                    .map_err(|_| SongKeyError::UnknownSeason(season))?;
+                Some(parsed)
+            }
+            Some(_) => return Err(SongKeyError::MissingSeparator),
+        };
+
+        let variant =
+            Weather::from_key_digit(variant).ok_or(SongKeyError::UnknownVariant(variant))?;
+
+        let hour: String = [tens, ones].iter().collect();
+        let key = match (tens.to_digit(10), ones.to_digit(10)) {
+            (Some(tens), Some(ones)) if tens * 10 + ones < 24 => {
+                SongKey::new(variant, tens * 10 + ones)
+            }
+            _ => return Err(SongKeyError::InvalidHour(hour)),
+        };
+        Ok(match season {
+            Some(season) => key.in_season(season),
+            None => key,
+        })
+    }
+}
+
+impl fmt::Display for SongKey {
+    /// Formats as the file name prefix, e.g. "117" for rainy at 5PM or "117-summer".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{:02}", self.variant.key_digit(), self.hour)?;
+        match self.season {
+            Some(season) => write!(f, "-{}", season),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A song file and how to play it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Song {
+    pub path: PathBuf,
+    /// Name from the manifest, shown instead of the file name.
+    pub title: Option<String>,
+    /// Gain in dB applied when the song plays.
+    pub gain: f32,
+    /// Gain in dB that brings the song to the loudness target, 0 when not normalized.
+    pub normalization: f32,
+    /// Seconds into the file where the loop starts, from the manifest or the file's tags.
+    pub loop_start: Option<f64>,
+    /// Length of the loop in seconds, from the manifest or the file's tags.
+    pub loop_length: Option<f64>,
+}
+
+/// The part of a song that repeats. Everything before `start` is an intro played once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopPoints {
+    pub start: Duration,
+    pub length: Duration,
+}
+
+impl Song {
+    /// A song named with the `VXX_` convention, played as it is.
+    pub fn new(path: PathBuf) -> Self {
+        Song {
+            path,
+            title: None,
+            gain: 0.0,
+            normalization: 0.0,
+            loop_start: None,
+            loop_length: None,
+        }
+    }
+
+    fn from_manifest(dir: &Path, entry: &ManifestEntry) -> Self {
+        Song {
+            path: dir.join(&entry.file),
+            title: entry.title.clone(),
+            gain: entry.gain,
+            normalization: 0.0,
+            loop_start: entry.loop_start,
+            loop_length: entry.loop_length,
+        }
+    }
+
+    /// The manifest title, or the file name without its extension.
+    pub fn title(&self) -> String {
+        match &self.title {
+            Some(title) => title.clone(),
+            None => self
+                .path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Where the song loops, `None` to loop the whole file. A loop without a length runs
+    /// to the end of the file, which needs the file's `duration`, and so does a loop whose
+    /// length runs past it. A loop starting past the end of the file would end the track,
+    /// so the whole file loops instead.
+    pub fn loop_points(&self, duration: Option<Duration>) -> Option<LoopPoints> {
+        if self.loop_start.is_none() && self.loop_length.is_none() {
+            return None;
+        }
+
+        let start = Duration::try_from_secs_f64(self.loop_start.unwrap_or(0.0)).ok()?;
+        if duration.is_some_and(|duration| start >= duration) {
+            return None;
+        }
+        let to_end = duration.map(|duration| duration - start);
+        let length = match self.loop_length {
+            Some(length) => {
+                let length = Duration::try_from_secs_f64(length).ok()?;
+                to_end.map_or(length, |to_end| length.min(to_end))
+            }
+            None => to_end?,
+        };
+        if length.is_zero() {
+            return None;
+        }
+        Some(LoopPoints { start, length })
+    }
+
+    /// The track volume that applies the song's gain and loudness normalization.
+    pub fn volume(&self) -> f32 {
+        10f32.powf((self.gain + self.normalization) / 20.0)
+    }
+}
+
+/// Every song found in a songs folder, grouped by key.
+pub struct Scan {
+    /// The songs for each key, more than one if a slot has duplicates.
+    pub songs: HashMap<SongKey, Vec<Song>>,
+    /// Files whose names are not valid song names.
+    pub invalid: Vec<(PathBuf, SongKeyError)>,
+    /// Manifest entries that could not be used.
+    pub manifest_errors: Vec<String>,
+}
+
+/// Reads the songs in `path`: the entries of its manifest, if it has one, then the files
+/// named with the `VXX_` convention. Subfolders, hidden files and README files are skipped.
+pub fn scan(path: &Path) -> Result<Scan, String> {
+    let mut songs: HashMap<SongKey, Vec<Song>> = HashMap::new();
+    let mut invalid = vec![];
+    let mut manifest_errors = vec![];
+
+    let manifest = Manifest::load(path)?.unwrap_or_default();
+    let mut listed = HashSet::new();
+    if let Some(chime) = &manifest.chime {
+        listed.insert(path.join(chime));
+    }
+    for entry in &manifest.songs {
+        listed.insert(path.join(&entry.file));
+        match entry.validate(path) {
+            Ok(()) => songs
+                .entry(entry.key())
+                .or_default()
+                .push(Song::from_manifest(path, entry)),
+            Err(e) => manifest_errors.push(e),
+        }
+    }
+
+    let read_error = |e: io::Error| format!("Could not read {}: {}", path.display(), e);
+    let mut files = vec![];
+    for entry in fs::read_dir(path).map_err(read_error)? {
+        let entry = entry.map_err(read_error)?;
+        if entry.file_type().map_err(read_error)?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+
+    for file_path in files {
+        let file_name = match file_path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name.to_string(),
+            None => {
+                println!("Skipping {}: name is not valid UTF-8.", file_path.display());
+                continue;
+            }
+        };
+        if file_name.starts_with('.')
+            || file_name.to_uppercase().starts_with("README")
+            || file_name == MANIFEST_FILE
+            || listed.contains(&file_path)
+        {
+            continue;
+        }
+
+        match SongKey::from_file_name(&file_name) {
+            Ok(key) => songs.entry(key).or_default().push(Song::new(file_path)),
+            Err(e) => invalid.push((file_path, e)),
+        }
+    }
+
+    Ok(Scan {
+        songs,
+        invalid,
+        manifest_errors,
+    })
+}
+
+/// Builds the song map of the songs folder at `path`, using the first song of any duplicated key.
+pub fn load_songs(path: &Path) -> Result<HashMap<SongKey, Song>, String> {
+    let scan = scan(path)?;
+    for e in &scan.manifest_errors {
+        println!("Skipping manifest entry {}", e);
+    }
+    for (file, e) in &scan.invalid {
+        println!("Skipping {}: {}", file.display(), e);
+    }
+
+    let mut song_map = HashMap::new();
+    for (key, mut songs) in scan.songs {
+        if songs.len() > 1 {
+            let files: Vec<_> = songs.iter().map(|song| &song.path).collect();
+            println!("Duplicate songs for {}: {:?}, using the first.", key, files);
+        }
+        song_map.insert(key, songs.remove(0));
+    }
+
+    // Loop points in the manifest override the file's tags.
+    for song in song_map.values_mut() {
+        if song.loop_start.is_some() || song.loop_length.is_some() {
+            continue;
+        }
+        match read_loop_tags(&song.path) {
+            Ok((start, length)) => {
+                song.loop_start = start;
+                song.loop_length = length;
+            }
+            Err(e) => {
+                println!("Not reading loop tags: {}", e);
+                break;
+            }
+        }
+    }
+    Ok(song_map)
+}
+
+/// The keys whose song is different in `new`: added, removed, pointing at another file or
+/// with other manifest settings, or whose file is one of `changed_files`.
+pub fn changed_keys(
+    old: &HashMap<SongKey, Song>,
+    new: &HashMap<SongKey, Song>,
+    changed_files: &HashSet<PathBuf>,
+) -> HashSet<SongKey> {
+    old.keys()
+        .chain(new.keys())
+        .filter(|key| match (old.get(key), new.get(key)) {
+            (Some(old_song), Some(new_song)) => {
+                old_song != new_song || changed_files.contains(&new_song.path)
+            }
+            _ => true,
+        })
+        .copied()
+        .collect()
+}
+
+/// Finds the song to play for `weather` at `hour` in `season` when not every slot of the
+/// library is filled. Tries the exact key, then the variants of `fallbacks` for the same
+/// hour, then the same hour's clear variant, then the nearest hour that has any of those
+/// variants. Each slot's seasonal arrangement is preferred over its season-less one.
+/// A library with none of those variants plays the song of any weather or season nearest
+/// to `hour`. Logs the substitution it made. Returns `None` only if the library is empty.
+pub fn resolve<'a>(
+    songs: &'a HashMap<SongKey, Song>,
+    weather: Weather,
+    hour: u32,
+    season: Option<Season>,
+    fallbacks: &[Weather],
+) -> Option<(SongKey, &'a Song)> {
+    let mut variants = vec![weather];
+    variants.extend(fallbacks.iter().copied());
+    variants.push(Weather::Clear);
+    variants.dedup();
+
+    let lookup = |key: SongKey| {
+        season
+            .map(|season| key.in_season(season))
+            .into_iter()
+            .chain([key])
+            .find_map(|key| songs.get(&key).map(|song| (key, song)))
+    };
+
+    let exact = SongKey::new(weather, hour);
+    if let Some(found) = lookup(exact) {
+        return Some(found);
+    }
+
+    // Same hour first, then one hour either side, then two...
+    for distance in 0..=12 {
+        let hours = [(hour + 24 - distance) % 24, (hour + distance) % 24];
+        for candidate_hour in hours {
+            for variant in &variants {
+                if let Some((key, song)) = lookup(SongKey::new(*variant, candidate_hour)) {
+                    println!(
+                        "No song for {:?} at {:02}:00 ({}), using {:?} at {:02}:00 ({}) instead.",
+                        weather, hour, exact, variant, candidate_hour, key
+                    );
+                    return Some((key, song));
+                }
+            }
+        }
+    }
+
+    let hours_away = |key: &SongKey| {
+        let distance = key.hour.abs_diff(hour) % 24;
+        distance.min(24 - distance)
+    };
+    let (key, song) = songs
+        .iter()
+        .min_by_key(|(key, _)| (hours_away(key), **key))?;
+    println!(
+        "No song for {:?} or its fallbacks, using {} instead.",
+        weather, key
+    );
+    Some((*key, song))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library(keys: &[SongKey]) -> HashMap<SongKey, Song> {
+        keys.iter()
+            .map(|key| (*key, Song::new(PathBuf::from(format!("{}.ogg", key)))))
+            .collect()
+    }
+
+    fn song(loop_start: Option<f64>, loop_length: Option<f64>) -> Song {
+        Song {
+            loop_start,
+            loop_length,
+            ..Song::new(PathBuf::from("117.ogg"))
+        }
+    }
+
+    #[test]
+    fn file_names_give_their_key() {
+        let rainy = SongKey::new(Weather::Rainy, 17);
+        assert_eq!(SongKey::from_file_name("117_5PM-Rainy.ogg"), Ok(rainy));
+        assert_eq!(SongKey::from_file_name("117.ogg"), Ok(rainy));
+        assert_eq!(SongKey::from_file_name("117"), Ok(rainy));
+        assert_eq!(
+            SongKey::from_file_name("023-summer_11PM.ogg"),
+            Ok(SongKey::new(Weather::Clear, 23).in_season(Season::Summer))
+        );
+        assert_eq!(
+            SongKey::from_file_name("200-winter.flac"),
+            Ok(SongKey::new(Weather::Snowy, 0).in_season(Season::Winter))
+        );
+    }
+
+    #[test]
+    fn short_file_names_are_too_short() {
+        for name in ["", "1", "11", "1.ogg", "11.ogg", "1_x.ogg", "11-summer.ogg"] {
+            assert_eq!(SongKey::from_file_name(name), Err(SongKeyError::TooShort));
+        }
+    }
+
+    #[test]
+    fn invalid_file_names_say_why() {
+        assert_eq!(
+            SongKey::from_file_name("917_5PM.ogg"),
+            Err(SongKeyError::UnknownVariant('9'))
+        );
+        assert_eq!(
+            SongKey::from_file_name("124_Midnight.ogg"),
+            Err(SongKeyError::InvalidHour(String::from("24")))
+        );
+        assert_eq!(
+            SongKey::from_file_name("199.ogg"),
+            Err(SongKeyError::InvalidHour(String::from("99")))
+        );
+        assert_eq!(
+            SongKey::from_file_name("117-monsoon.ogg"),
+            Err(SongKeyError::UnknownSeason(String::from("monsoon")))
+        );
+        assert_eq!(
+            SongKey::from_file_name("1170.ogg"),
+            Err(SongKeyError::MissingSeparator)
+        );
+    }
+
+    #[test]
+    fn non_ascii_file_names_do_not_panic() {
+        assert_eq!(
+            SongKey::from_file_name("é17_Pluie.ogg"),
+            Err(SongKeyError::UnknownVariant('é'))
+        );
+        assert_eq!(
+            SongKey::from_file_name("1１7_5PM.ogg"),
+            Err(SongKeyError::InvalidHour(String::from("１7")))
+        );
+        assert_eq!(
+            SongKey::from_file_name("117-été.ogg"),
+            Err(SongKeyError::UnknownSeason(String::from("été")))
+        );
+        assert_eq!(
+            SongKey::from_file_name("117雨.ogg"),
+            Err(SongKeyError::MissingSeparator)
+        );
+        assert_eq!(
+            SongKey::from_file_name("雨.ogg"),
+            Err(SongKeyError::TooShort)
+        );
+    }
+
+    #[test]
+    fn loop_points_need_a_start_or_length() {
+        let duration = Some(Duration::from_secs(100));
+        assert_eq!(song(None, None).loop_points(duration), None);
+        assert_eq!(
+            song(Some(4.5), Some(90.0)).loop_points(None),
+            Some(LoopPoints {
+                start: Duration::from_millis(4500),
+                length: Duration::from_secs(90),
+            })
+        );
+        assert_eq!(
+            song(None, Some(30.0)).loop_points(None),
+            Some(LoopPoints {
+                start: Duration::ZERO,
+                length: Duration::from_secs(30),
+            })
+        );
+    }
+
+    #[test]
+    fn loop_without_length_runs_to_the_end() {
+        let duration = Some(Duration::from_secs(100));
+        assert_eq!(
+            song(Some(20.0), None).loop_points(duration),
+            Some(LoopPoints {
+                start: Duration::from_secs(20),
+                length: Duration::from_secs(80),
+            })
+        );
+        assert_eq!(song(Some(20.0), None).loop_points(None), None);
+    }
+
+    #[test]
+    fn loop_past_the_end_runs_to_the_end() {
+        let duration = Some(Duration::from_secs(100));
+        assert_eq!(
+            song(Some(20.0), Some(200.0)).loop_points(duration),
+            Some(LoopPoints {
+                start: Duration::from_secs(20),
+                length: Duration::from_secs(80),
+            })
+        );
+        assert_eq!(
+            song(Some(20.0), Some(200.0)).loop_points(None),
+            Some(LoopPoints {
+                start: Duration::from_secs(20),
+                length: Duration::from_secs(200),
+            })
+        );
+    }
+
+    #[test]
+    fn out_of_range_loop_points_loop_the_whole_file() {
+        let duration = Some(Duration::from_secs(100));
+        assert_eq!(song(Some(120.0), None).loop_points(duration), None);
+        assert_eq!(song(Some(100.0), None).loop_points(duration), None);
+        assert_eq!(song(Some(120.0), Some(10.0)).loop_points(duration), None);
+        assert_eq!(song(Some(100.0), Some(10.0)).loop_points(duration), None);
+        assert_eq!(song(Some(-1.0), Some(10.0)).loop_points(duration), None);
+        assert_eq!(song(Some(10.0), Some(-1.0)).loop_points(duration), None);
+        assert_eq!(song(Some(10.0), Some(0.0)).loop_points(duration), None);
+        assert_eq!(song(Some(f64::NAN), None).loop_points(duration), None);
+    }
+
+    #[test]
+    fn resolve_plays_the_exact_key() {
+        let songs = library(&[
+            SongKey::new(Weather::Clear, 8),
+            SongKey::new(Weather::Rainy, 8),
+            SongKey::new(Weather::Thunderstorm, 8),
+        ]);
+        let fallbacks = [Weather::Rainy];
+        let (key, _) = resolve(&songs, Weather::Thunderstorm, 8, None, &fallbacks).unwrap();
+        assert_eq!(key, SongKey::new(Weather::Thunderstorm, 8));
+    }
+
+    #[test]
+    fn resolve_tries_the_fallbacks_in_order() {
+        let songs = library(&[
+            SongKey::new(Weather::Clear, 8),
+            SongKey::new(Weather::Rainy, 8),
+            SongKey::new(Weather::Cloudy, 8),
+        ]);
+        let fallbacks = [Weather::Cloudy, Weather::Rainy];
+        let (key, _) = resolve(&songs, Weather::Thunderstorm, 8, None, &fallbacks).unwrap();
+        assert_eq!(key, SongKey::new(Weather::Cloudy, 8));
+        let fallbacks = [Weather::Rainy, Weather::Cloudy];
+        let (key, _) = resolve(&songs, Weather::Thunderstorm, 8, None, &fallbacks).unwrap();
+        assert_eq!(key, SongKey::new(Weather::Rainy, 8));
+    }
+
+    #[test]
+    fn resolve_plays_clear_at_the_same_hour_before_other_hours() {
+        let songs = library(&[
+            SongKey::new(Weather::Clear, 8),
+            SongKey::new(Weather::Thunderstorm, 9),
+            SongKey::new(Weather::Rainy, 9),
+        ]);
+        let fallbacks = [Weather::Rainy];
+        let (key, _) = resolve(&songs, Weather::Thunderstorm, 8, None, &fallbacks).unwrap();
+        assert_eq!(key, SongKey::new(Weather::Clear, 8));
+    }
+
+    #[test]
+    fn resolve_plays_the_nearest_hour() {
+        let songs = library(&[
+            SongKey::new(Weather::Clear, 7),
+            SongKey::new(Weather::Clear, 12),
+        ]);
+        let (key, _) = resolve(&songs, Weather::Clear, 10, None, &[]).unwrap();
+        assert_eq!(key, SongKey::new(Weather::Clear, 12));
+        let (key, _) = resolve(&songs, Weather::Clear, 9, None, &[]).unwrap();
+        assert_eq!(key, SongKey::new(Weather::Clear, 7));
+
+        // The earlier hour wins a tie, and the weather's own variant an hour away beats
+        // clear two hours away.
+        let songs = library(&[
+            SongKey::new(Weather::Rainy, 6),
+            SongKey::new(Weather::Rainy, 10),
+            SongKey::new(Weather::Clear, 11),
+        ]);
+        let (key, _) = resolve(&songs, Weather::Rainy, 8, None, &[]).unwrap();
+        assert_eq!(key, SongKey::new(Weather::Rainy, 6));
+        let (key, _) = resolve(&songs, Weather::Rainy, 9, None, &[]).unwrap();
+        assert_eq!(key, SongKey::new(Weather::Rainy, 10));
+    }
+
+    #[test]
+    fn resolve_wraps_past_midnight() {
+        let songs = library(&[
+            SongKey::new(Weather::Clear, 1),
+            SongKey::new(Weather::Clear, 20),
+        ]);
+        let (key, _) = resolve(&songs, Weather::Clear, 23, None, &[]).unwrap();
+        assert_eq!(key, SongKey::new(Weather::Clear, 1));
+
+        let songs = library(&[
+            SongKey::new(Weather::Clear, 3),
+            SongKey::new(Weather::Clear, 22),
+        ]);
+        let (key, _) = resolve(&songs, Weather::Clear, 0, None, &[]).unwrap();
+        assert_eq!(key, SongKey::new(Weather::Clear, 22));
+    }
+
+    #[test]
+    fn resolve_prefers_the_seasonal_song() {
+        let winter = SongKey::new(Weather::Clear, 8).in_season(Season::Winter);
+        let songs = library(&[SongKey::new(Weather::Clear, 8), winter]);
+        let (key, _) = resolve(&songs, Weather::Clear, 8, Some(Season::Winter), &[]).unwrap();
+        assert_eq!(key, winter);
+        let (key, _) = resolve(&songs, Weather::Rainy, 8, Some(Season::Winter), &[]).unwrap();
+        assert_eq!(key, winter);
+        let (key, _) = resolve(&songs, Weather::Clear, 8, Some(Season::Summer), &[]).unwrap();
+        assert_eq!(key, SongKey::new(Weather::Clear, 8));
+        let (key, _) = resolve(&songs, Weather::Clear, 8, None, &[]).unwrap();
+        assert_eq!(key, SongKey::new(Weather::Clear, 8));
+    }
+
+    #[test]
+    fn resolve_plays_other_weathers_without_clear_songs() {
+        let songs = library(&[
+            SongKey::new(Weather::Rainy, 3),
+            SongKey::new(Weather::Rainy, 9),
+        ]);
+        let (key, _) = resolve(&songs, Weather::Clear, 8, None, &[]).unwrap();
+        assert_eq!(key, SongKey::new(Weather::Rainy, 9));
+    }
+
+    #[test]
+    fn resolve_plays_other_seasons() {
+        let songs = library(&[SongKey::new(Weather::Clear, 12).in_season(Season::Summer)]);
+        let (key, _) = resolve(&songs, Weather::Clear, 12, Some(Season::Winter), &[]).unwrap();
+        assert_eq!(key.season, Some(Season::Summer));
+    }
+
+    #[test]
+    fn resolve_needs_a_song() {
+        assert!(resolve(&HashMap::new(), Weather::Clear, 0, None, &[]).is_none());
+    }
+}
@@ -0,0 +1,52 @@
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use serde_json::Value;
+
+/// Reads the `LOOPSTART` and `LOOPLENGTH` tags game rips carry, converted from samples to
+/// seconds. A file without the tags, or that ffprobe cannot read, has no loop points.
+/// Fails only if ffprobe cannot be run at all.
+pub fn read_loop_tags(path: &Path) -> Result<(Option<f64>, Option<f64>), String> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-of", "json"])
+        .args([
+            "-show_entries",
+            "format_tags:stream_tags:stream=sample_rate",
+        ])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => String::from("ffprobe not found"),
+            _ => format!("could not run ffprobe: {}", e),
+        })?;
+    if !output.status.success() {
+        return Ok((None, None));
+    }
+
+    let probe: Value = match serde_json::from_slice(&output.stdout) {
+        Ok(probe) => probe,
+        Err(_) => return Ok((None, None)),
+    };
+    let stream = &probe["streams"][0];
+    let sample_rate = match stream["sample_rate"]
+        .as_str()
+        .and_then(|rate| rate.parse::<f64>().ok())
+    {
+        Some(rate) if rate > 0.0 => rate,
+        _ => return Ok((None, None)),
+    };
+
+    // Vorbis comments end up on the stream, tags of other formats on the file.
+    let tag = |name: &str| {
+        [&stream["tags"], &probe["format"]["tags"]]
+            .iter()
+            .filter_map(|tags| tags.as_object())
+            .flat_map(|tags| tags.iter())
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .and_then(|(_, value)| value.as_str()?.trim().parse::<f64>().ok())
+            .map(|samples| samples / sample_rate)
+    };
+    Ok((tag("LOOPSTART"), tag("LOOPLENGTH")))
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+
+/// Quietest integrated loudness ffmpeg reports, given for silent files.
+const SILENCE: f64 = -70.0;
+
+/// What ffmpeg's EBU R128 meter reports for a song.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loudness {
+    /// Integrated loudness in LUFS.
+    pub integrated: f64,
+    /// True peak in dBTP.
+    pub peak: f64,
+}
+
+impl Loudness {
+    /// Gain in dB that brings the song to `target` LUFS. Songs are only made louder as far
+    /// as their true peak stays under 0 dBTP, so quiet songs with loud peaks do not clip.
+    pub fn gain_to(&self, target: f64) -> f64 {
+        (target - self.integrated).min((-self.peak).max(0.0))
+    }
+}
+
+/// A measured file, with what it looked like when it was measured.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Measurement {
+    size: u64,
+    modified: u64,
+    /// Integrated loudness in LUFS.
+    loudness: f64,
+    /// True peak in dBTP, missing from caches written before peaks were measured.
+    #[serde(default)]
+    peak: Option<f64>,
+}
+
+/// Loudness of every song measured so far, saved as JSON so files are only measured again
+/// when they change.
+pub struct LoudnessCache {
+    path: PathBuf,
+    files: HashMap<String, Measurement>,
+    changed: bool,
+}
+
+impl LoudnessCache {
+    /// Reads the cache at `path`, starting empty if it does not exist or cannot be read.
+    pub fn load(path: &Path) -> Self {
+        let files = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                println!("Ignoring invalid {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                println!("Could not read {}: {}", path.display(), e);
+                HashMap::new()
+            }
+        };
+
+        LoudnessCache {
+            path: path.to_path_buf(),
+            files,
+            changed: false,
+        }
+    }
+
+    /// The loudness of `file`, measured with ffmpeg if the cache has no measurement of its
+    /// current version. `None` for silent files and files that cannot be measured. Fails
+    /// only if ffmpeg cannot be run at all.
+    pub fn loudness(&mut self, file: &Path) -> Result<Option<Loudness>, String> {
+        let metadata = match fs::metadata(file) {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(None),
+        };
+        let size = metadata.len();
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |modified| modified.as_secs());
+
+        let key = file.to_string_lossy().into_owned();
+        let cached = self.files.get(&key).and_then(|cached| {
+            let current = cached.size == size && cached.modified == modified;
+            let peak = cached.peak.filter(|_| current)?;
+            Some(Loudness {
+                integrated: cached.loudness,
+                peak,
+            })
+        });
+        let loudness = match cached {
+            Some(loudness) => loudness,
+            None => {
+                println!("Measuring the loudness of {}", file.display());
+                let loudness = match measure(file)? {
+                    Ok(loudness) => loudness,
+                    Err(e) => {
+                        println!("Could not measure {}: {}", file.display(), e);
+                        return Ok(None);
+                    }
+                };
+                self.files.insert(
+                    key,
+                    Measurement {
+                        size,
+                        modified,
+                        loudness: loudness.integrated,
+                        peak: Some(loudness.peak),
+                    },
+                );
+                self.changed = true;
+                loudness
+            }
+        };
+
+        if loudness.integrated <= SILENCE {
+            Ok(None)
+        } else {
+            Ok(Some(loudness))
+        }
+    }
+
+    /// Writes the cache back to disk if anything new was measured.
+    pub fn save(&self) -> io::Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        let contents = serde_json::to_string_pretty(&self.files)?;
+        fs::write(&self.path, contents)
+    }
+}
+
+/// Runs the file through ffmpeg's EBU R128 meter and reads the loudness from its summary.
+/// The outer error is for ffmpeg not running, the inner one for this file.
+fn measure(file: &Path) -> Result<Result<Loudness, String>, String> {
+    let output = Command::new("ffmpeg")
+        .args(["-nostats", "-i"])
+        .arg(file)
+        .args(["-filter:a", "ebur128=peak=true", "-f", "null", "-"])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("could not run ffmpeg: {}", e))?;
+    if !output.status.success() {
+        return Ok(Err(String::from("ffmpeg failed")));
+    }
+
+    let loudness = parse_summary(&String::from_utf8_lossy(&output.stderr))
+        .ok_or_else(|| String::from("no loudness in the ffmpeg output"));
+    Ok(loudness)
+}
+
+/// Reads the integrated loudness and true peak from the summary ffmpeg's meter prints last.
+fn parse_summary(output: &str) -> Option<Loudness> {
+    // Only the summary lines start with "I:" and "Peak:", the per-frame lines start with
+    // the filter name.
+    let value = |label: &str, unit: &str| {
+        output.lines().rev().find_map(|line| {
+            line.trim()
+                .strip_prefix(label)?
+                .trim()
+                .strip_suffix(unit)?
+                .trim()
+                .parse()
+                .ok()
+        })
+    };
+    Some(Loudness {
+        integrated: value("I:", "LUFS")?,
+        peak: value("Peak:", "dBFS")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUMMARY: &str = "\
+[Parsed_ebur128_0 @ 0x5581] t: 95.3  TARGET:-23 LUFS    M: -17.2 S: -16.9     I: -16.4 LUFS       LRA:   6.2 LU  FTPK:  -1.2  -1.4 dBFS  TPK:  -0.4  -0.5 dBFS
+[Parsed_ebur128_0 @ 0x5581] Summary:
+
+  Integrated loudness:
+    I:         -16.4 LUFS
+    Threshold: -26.8 LUFS
+
+  Loudness range:
+    LRA:         6.2 LU
+    Threshold: -36.8 LUFS
+    LRA low:   -20.7 LUFS
+    LRA high:  -14.5 LUFS
+
+  True peak:
+    Peak:       -0.4 dBFS
+";
+
+    #[test]
+    fn summary_gives_loudness_and_peak() {
+        let loudness = Loudness {
+            integrated: -16.4,
+            peak: -0.4,
+        };
+        assert_eq!(parse_summary(SUMMARY), Some(loudness));
+        assert_eq!(parse_summary("Summary:\n    I: -16.4 LUFS\n"), None);
+    }
+
+    #[test]
+    fn gain_stops_before_the_peak_clips() {
+        let quiet = Loudness {
+            integrated: -30.0,
+            peak: -6.0,
+        };
+        assert_eq!(quiet.gain_to(-18.0), 6.0);
+        assert_eq!(quiet.gain_to(-33.0), -3.0);
+
+        let clipping = Loudness {
+            integrated: -24.0,
+            peak: 1.5,
+        };
+        assert_eq!(clipping.gain_to(-18.0), 0.0);
+        assert_eq!(clipping.gain_to(-26.0), -2.0);
+    }
+}
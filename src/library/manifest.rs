@@ -0,0 +1,90 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::SongKey;
+use crate::season::Season;
+use crate::weather::Weather;
+
+/// Manifest file looked for in a songs folder.
+pub const MANIFEST_FILE: &str = "songs.toml";
+
+/// Maps files to slots without the `VXX_` naming convention, see songs/README.txt.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Manifest {
+    /// Chime file played at the top of the hour, relative to the songs folder.
+    pub chime: Option<String>,
+    #[serde(rename = "song")]
+    pub songs: Vec<ManifestEntry>,
+}
+
+/// One `[[song]]` of the manifest.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestEntry {
+    /// Path of the file, relative to the songs folder.
+    pub file: String,
+    pub hour: u32,
+    #[serde(default = "clear")]
+    pub weather: Weather,
+    /// Season the song is an arrangement for, played in every season if left out.
+    pub season: Option<Season>,
+    /// Name shown instead of the file name.
+    pub title: Option<String>,
+    /// Gain in dB applied when the song plays.
+    #[serde(default)]
+    pub gain: f32,
+    /// Seconds into the file where the loop starts.
+    pub loop_start: Option<f64>,
+    /// Length of the loop in seconds.
+    pub loop_length: Option<f64>,
+}
+
+fn clear() -> Weather {
+    Weather::Clear
+}
+
+impl Manifest {
+    /// Reads the manifest of the songs folder at `dir`, `None` if it has none.
+    pub fn load(dir: &Path) -> Result<Option<Self>, String> {
+        let path = dir.join(MANIFEST_FILE);
+        match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map(Some)
+                .map_err(|e| format!("Invalid {}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Could not read {}: {}", path.display(), e)),
+        }
+    }
+}
+
+impl ManifestEntry {
+    /// The slot the entry's song plays in.
+    pub fn key(&self) -> SongKey {
+        let key = SongKey::new(self.weather, self.hour);
+        match self.season {
+            Some(season) => key.in_season(season),
+            None => key,
+        }
+    }
+
+    /// Why the entry cannot be used, if it cannot.
+    pub fn validate(&self, dir: &Path) -> Result<(), String> {
+        if self.hour > 23 {
+            return Err(format!("{}: hour {} is not 0 to 23", self.file, self.hour));
+        }
+        if self.weather == Weather::Unknown {
+            return Err(format!("{}: \"unknown\" is not a song variant", self.file));
+        }
+        if !dir.join(&self.file).is_file() {
+            return Err(format!("{}: file not found", self.file));
+        }
+        if matches!(self.loop_length, Some(length) if length <= 0.0) {
+            return Err(format!("{}: loop_length must be positive", self.file));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::{load_songs, LoudnessCache, Manifest, Song, SongKey};
+use crate::settings::SongSettings;
+
+/// One soundtrack: a songs folder and the songs found in it. Cloning it is cheap, so
+/// guilds take a copy and let go of the shared packs before fetching the weather or
+/// reading songs.
+#[derive(Clone)]
+pub struct Pack {
+    pub path: PathBuf,
+    pub songs: Arc<HashMap<SongKey, Song>>,
+    /// Played at the top of the hour before the new hour's song.
+    pub chime: Option<PathBuf>,
+}
+
+/// Every pack the bot can play, by name.
+#[derive(Clone)]
+pub struct Packs {
+    pub packs: BTreeMap<String, Pack>,
+    /// Pack played by guilds that have not picked one.
+    pub default: String,
+}
+
+/// The songs folders the settings point at, by pack name. With a packs folder set, every
+/// subfolder of it is a pack named after the subfolder. Otherwise the songs folder is the
+/// only pack.
+pub fn pack_dirs(settings: &SongSettings) -> Result<BTreeMap<String, PathBuf>, String> {
+    let packs_path = match &settings.packs {
+        Some(packs_path) => Path::new(packs_path),
+        None => {
+            let path = Path::new(&settings.path);
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| String::from("default"));
+            return Ok(BTreeMap::from([(name, path.to_path_buf())]));
+        }
+    };
+
+    let read_error = |e| format!("Could not read {}: {}", packs_path.display(), e);
+    let mut dirs = BTreeMap::new();
+    for entry in fs::read_dir(packs_path).map_err(read_error)? {
+        let entry = entry.map_err(read_error)?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type().map_err(read_error)?.is_dir() && !name.starts_with('.') {
+            dirs.insert(name, entry.path());
+        }
+    }
+    Ok(dirs)
+}
+
+/// The chime of the manifest in `path`, or else the one in the settings.
+fn find_chime(settings: &SongSettings, path: &Path) -> Option<PathBuf> {
+    let chime = match Manifest::load(path) {
+        Ok(Some(Manifest {
+            chime: Some(chime), ..
+        })) => path.join(chime),
+        _ => PathBuf::from(settings.chime.as_ref()?),
+    };
+    if chime.is_file() {
+        Some(chime)
+    } else {
+        println!("Chime {} not found.", chime.display());
+        None
+    }
+}
+
+/// Sets the gain that brings every song to `target` LUFS, or as close as it gets without
+/// clipping.
+fn normalize(songs: &mut HashMap<SongKey, Song>, target: f64, cache: &mut LoudnessCache) {
+    for song in songs.values_mut() {
+        match cache.loudness(&song.path) {
+            Ok(Some(loudness)) => song.normalization = loudness.gain_to(target) as f32,
+            Ok(None) => {}
+            Err(e) => {
+                println!("Not normalizing loudness: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+impl Packs {
+    /// Reads the songs of every pack. A pack that cannot be read is skipped with a warning.
+    pub fn load(settings: &SongSettings) -> Result<Self, String> {
+        Packs::read(settings, |_, _| None)
+    }
+
+    /// Reads the packs again after `changed_files` changed. Packs with none of those files
+    /// in their folder keep their songs, so ffprobe only reads the changed packs again.
+    pub fn reload(
+        &self,
+        settings: &SongSettings,
+        changed_files: &HashSet<PathBuf>,
+    ) -> Result<Self, String> {
+        Packs::read(settings, |name, path| {
+            let pack = self.packs.get(name)?;
+            let changed =
+                pack.path != path || changed_files.iter().any(|file| file.starts_with(path));
+            (!changed).then(|| pack.clone())
+        })
+    }
+
+    /// Reads the songs of every pack that `unchanged` gives no pack for, by name and folder.
+    fn read(
+        settings: &SongSettings,
+        unchanged: impl Fn(&str, &Path) -> Option<Pack>,
+    ) -> Result<Self, String> {
+        let mut loudness = settings
+            .loudness_target
+            .map(|_| LoudnessCache::load(Path::new(&settings.loudness_cache)));
+
+        let mut packs = BTreeMap::new();
+        for (name, path) in pack_dirs(settings)? {
+            if let Some(pack) = unchanged(&name, &path) {
+                packs.insert(name, pack);
+                continue;
+            }
+            match load_songs(&path) {
+                Ok(mut songs) => {
+                    if let (Some(target), Some(cache)) = (settings.loudness_target, &mut loudness) {
+                        normalize(&mut songs, target, cache);
+                    }
+                    println!("Pack {}: {} songs found.", name, songs.len());
+                    let chime = find_chime(settings, &path);
+                    let songs = Arc::new(songs);
+                    packs.insert(name, Pack { path, songs, chime });
+                }
+                Err(e) => println!("Skipping pack {}: {}", name, e),
+            }
+        }
+
+        if let Some(cache) = &loudness {
+            if let Err(e) = cache.save() {
+                println!("Could not save {}: {}", settings.loudness_cache, e);
+            }
+        }
+
+        let default = match &settings.default_pack {
+            Some(name) if packs.contains_key(name) => name.clone(),
+            Some(name) => return Err(format!("Default pack {} not found.", name)),
+            None => packs
+                .keys()
+                .next()
+                .cloned()
+                .ok_or_else(|| String::from("No song packs found."))?,
+        };
+
+        Ok(Packs { packs, default })
+    }
+
+    /// The pack called `name`, or the default pack if there is none by that name.
+    pub fn get(&self, name: Option<&str>) -> (&str, &Pack) {
+        match name.and_then(|name| self.packs.get_key_value(name)) {
+            Some((name, pack)) => (name, pack),
+            None => (&self.default, &self.packs[&self.default]),
+        }
+    }
+
+    /// The songs of the pack called `name`, or of the default pack.
+    pub fn songs(&self, name: Option<&str>) -> &HashMap<SongKey, Song> {
+        &self.get(name).1.songs
+    }
+}
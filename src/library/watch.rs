@@ -0,0 +1,72 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+/// How long the folder has to stay quiet before a burst of changes is reported.
+/// Copying a song in fires many events, and it should be read once it is complete.
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Watches a songs folder and everything under it for changed files.
+pub struct SongWatcher {
+    dir: PathBuf,
+    canonical_dir: PathBuf,
+    events: mpsc::UnboundedReceiver<notify::Result<notify::Event>>,
+    // Stops watching when dropped.
+    _watcher: RecommendedWatcher,
+}
+
+impl SongWatcher {
+    pub fn new(dir: &Path) -> notify::Result<Self> {
+        let canonical_dir = dir.canonicalize()?;
+        let (sender, events) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+        })?;
+        watcher.watch(&canonical_dir, RecursiveMode::Recursive)?;
+
+        Ok(SongWatcher {
+            dir: dir.to_path_buf(),
+            canonical_dir,
+            events,
+            _watcher: watcher,
+        })
+    }
+
+    /// Waits for files to change and returns their paths once the folder settles, written
+    /// the way the library writes them so they can be compared with `Song::path`.
+    pub async fn changed(&mut self) -> Option<HashSet<PathBuf>> {
+        let mut paths = HashSet::new();
+        let first = self.events.recv().await?;
+        self.collect(first, &mut paths);
+
+        while let Ok(event) = tokio::time::timeout(SETTLE_TIME, self.events.recv()).await {
+            match event {
+                Some(event) => self.collect(event, &mut paths),
+                None => break,
+            }
+        }
+        Some(paths)
+    }
+
+    fn collect(&self, event: notify::Result<notify::Event>, paths: &mut HashSet<PathBuf>) {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                println!("Error watching {}: {}", self.dir.display(), e);
+                return;
+            }
+        };
+        if event.kind.is_access() {
+            return;
+        }
+        for path in event.paths {
+            match path.strip_prefix(&self.canonical_dir) {
+                Ok(relative) => paths.insert(self.dir.join(relative)),
+                Err(_) => paths.insert(path),
+            };
+        }
+    }
+}
@@ -0,0 +1,195 @@
+extern crate songbird;
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use songbird::input::reader::MediaSource;
+use songbird::input::{utils, Codec, Container, Input, Reader};
+
+use crate::library::LoopPoints;
+
+/// Length of the frames that compressed songs are decoded and seeked in.
+const FRAME: Duration = Duration::from_millis(20);
+
+/// `points` moved to the nearest 20 ms frame, the steps that songs can be seeked in. The
+/// loop is at least one frame long.
+pub fn frame_aligned(points: LoopPoints) -> LoopPoints {
+    let frames =
+        |time: Duration| ((time.as_micros() + FRAME.as_micros() / 2) / FRAME.as_micros()) as u32;
+    let start = frames(points.start);
+    let end = frames(points.start + points.length).max(start + 1);
+    LoopPoints {
+        start: FRAME * start,
+        length: FRAME * (end - start),
+    }
+}
+
+/// Plays `song` from the start, then repeats the part between `points` for as long as the
+/// track plays. The loop points are moved to the nearest 20 ms frame, see [`frame_aligned`].
+pub fn intro_loop(song: Input, points: LoopPoints) -> Input {
+    let points = frame_aligned(points);
+    let stereo = song.stereo;
+    let frame = utils::timestamp_to_byte_count(FRAME, stereo) as u64;
+    let start = utils::timestamp_to_byte_count(points.start, stereo) as u64;
+    let end = start + utils::timestamp_to_byte_count(points.length, stereo) as u64;
+    let metadata = *song.metadata.clone();
+
+    let source = IntroLoop {
+        song: Mutex::new(song),
+        frame,
+        start,
+        end,
+        position: 0,
+    };
+    Input::new(
+        stereo,
+        Reader::Extension(Box::new(source)),
+        Codec::FloatPcm,
+        Container::Raw,
+        Some(metadata),
+    )
+}
+
+/// A decoded song that jumps back to its loop start whenever it reaches the loop end. The
+/// jump happens as the audio thread reads the song, so the loop has no gap and the track
+/// never wraps around to the intro.
+struct IntroLoop {
+    /// Only behind a mutex because sources must be `Sync`, which inputs are not. Reads and
+    /// seeks have the source to themselves and never lock it.
+    song: Mutex<Input>,
+    /// Bytes in one frame of the decoded song.
+    frame: u64,
+    /// Where the loop starts in the decoded song, in bytes.
+    start: u64,
+    /// Where the loop ends in the decoded song, in bytes.
+    end: u64,
+    /// Where the next read comes from in the decoded song, in bytes.
+    position: u64,
+}
+
+impl IntroLoop {
+    fn song(&mut self) -> &mut Input {
+        self.song.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Read for IntroLoop {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.end {
+            self.seek(SeekFrom::Start(self.start))?;
+        }
+
+        // Opus songs cannot be read across the end of a frame in one go.
+        let len = (buf.len() as u64)
+            .min(self.frame - self.position % self.frame)
+            .min(self.end - self.position) as usize;
+        let read = self.song().read(&mut buf[..len])?;
+        if read == 0 && self.position > 0 {
+            if self.position <= self.start {
+                // The file ends before the loop starts, so the whole file loops instead.
+                self.start = 0;
+            }
+            // The file ends before the loop does, so the loop ends with the file.
+            self.end = self.position;
+            return self.read(buf);
+        }
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for IntroLoop {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = self.song().seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+impl MediaSource for IntroLoop {
+    fn is_seekable(&self) -> bool {
+        let song = self.song.lock().unwrap_or_else(PoisonError::into_inner);
+        song.is_seekable()
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// A stereo song whose samples count up, `frames` 20 ms frames long.
+    fn counting_song(frames: usize) -> Input {
+        let samples = frames * utils::timestamp_to_sample_count(FRAME, true);
+        let bytes = (0..samples)
+            .flat_map(|sample| (sample as f32).to_le_bytes())
+            .collect::<Vec<_>>();
+        Input::float_pcm(true, Reader::Extension(Box::new(Cursor::new(bytes))))
+    }
+
+    /// The first sample of each of the next `frames` frames read from `song`.
+    fn frame_starts(song: &mut Input, frames: usize) -> Vec<usize> {
+        let frame_len = utils::timestamp_to_byte_count(FRAME, true);
+        let frame_samples = utils::timestamp_to_sample_count(FRAME, true);
+        (0..frames)
+            .map(|_| {
+                let mut frame = vec![0; frame_len];
+                song.read_exact(&mut frame).unwrap();
+                let first = f32::from_le_bytes(frame[..4].try_into().unwrap());
+                first as usize / frame_samples
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loop_points_snap_to_frames() {
+        let points = LoopPoints {
+            start: Duration::from_millis(1009),
+            length: Duration::from_millis(3),
+        };
+        let aligned = LoopPoints {
+            start: Duration::from_millis(1000),
+            length: FRAME,
+        };
+        assert_eq!(frame_aligned(points), aligned);
+    }
+
+    #[test]
+    fn intro_plays_once_then_the_loop_repeats() {
+        let points = LoopPoints {
+            start: FRAME,
+            length: FRAME * 2,
+        };
+        let mut song = intro_loop(counting_song(5), points);
+        assert_eq!(frame_starts(&mut song, 7), [0, 1, 2, 1, 2, 1, 2]);
+    }
+
+    #[test]
+    fn loop_past_the_end_ends_with_the_file() {
+        let points = LoopPoints {
+            start: FRAME * 2,
+            length: FRAME * 10,
+        };
+        let mut song = intro_loop(counting_song(4), points);
+        assert_eq!(frame_starts(&mut song, 6), [0, 1, 2, 3, 2, 3]);
+
+        let points = LoopPoints {
+            start: FRAME * 5,
+            length: FRAME * 2,
+        };
+        let mut song = intro_loop(counting_song(3), points);
+        assert_eq!(frame_starts(&mut song, 7), [0, 1, 2, 0, 1, 2, 0]);
+
+        let points = LoopPoints {
+            start: FRAME * 3,
+            length: FRAME * 2,
+        };
+        let mut song = intro_loop(counting_song(3), points);
+        assert_eq!(frame_starts(&mut song, 5), [0, 1, 2, 0, 1]);
+    }
+}
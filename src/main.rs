@@ -7,20 +7,37 @@
 //! features = ["client", "standard_framework", "voice"]
 //! ```
 
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
 use std::sync::{Arc, Weak};
-use std::{env, fs, vec};
-
+use std::{env, process};
+
+use nooku::ambience::*;
+use nooku::check::*;
+use nooku::guilds::*;
+use nooku::library::*;
+use nooku::looping::*;
+use nooku::season::*;
+use nooku::session::*;
+use nooku::settings::*;
+use nooku::timezone::*;
+use nooku::transition::*;
 use nooku::weather::*;
 
+use serenity::builder::CreateEmbed;
 use serenity::http::Http;
-use serenity::model::id::ChannelId;
+use serenity::model::application::command::{Command, CommandOptionType};
+use serenity::model::application::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOptionValue,
+};
+use serenity::model::application::interaction::Interaction;
+use serenity::model::id::{ChannelId, GuildId, UserId};
 
 use serenity::prelude::{Mentionable, Mutex, TypeMapKey};
 // This trait adds the `register_songbird` and `register_songbird_with` methods
 // to the client builder below, making it easy to install this voice client.
 // The voice client can be retrieved in any command using `songbird::get(ctx).await`.
+use songbird::tracks::TrackHandle;
 use songbird::{SerenityInit, TrackEvent};
 
 // Import the `Context` to handle commands.
@@ -32,7 +49,7 @@ use serenity::{
     framework::{
         standard::{
             macros::{command, group},
-            CommandResult,
+            Args, CommandResult,
         },
         StandardFramework,
     },
@@ -42,135 +59,293 @@ use serenity::{
 };
 
 use chrono::*;
+use chrono_tz::Tz;
 use songbird::{
     driver::Bitrate,
     input::{self, cached::Compressed},
     Call, Event, EventContext, EventHandler as VoiceEventHandler,
 };
 
-const API_KEY: &str = include_str!("../api_key");
-const LOCATION: Location = Location {
-    latitude: 34.221924,
-    longitude: -79.814693,
-};
+/// Slash commands without options, with their descriptions.
+const SIMPLE_COMMANDS: [(&str, &str); 11] = [
+    (
+        "play",
+        "Join your voice channel and play the song for this hour",
+    ),
+    ("join", "Join your voice channel"),
+    ("leave", "Leave the voice channel"),
+    ("mute", "Mute the bot"),
+    ("unmute", "Unmute the bot"),
+    ("deafen", "Deafen the bot"),
+    ("undeafen", "Undeafen the bot"),
+    ("ping", "Check that the bot is running"),
+    ("weather", "Show the weather at this server's location"),
+    ("packs", "List the song packs"),
+    ("nowplaying", "Show the song playing and when it changes"),
+];
+
+/// Outcome of a command shared by the prefix and slash versions: what was done, or why
+/// nothing was.
+type Reply = Result<String, String>;
 
 struct Handler;
 
 #[async_trait]
 impl EventHandler for Handler {
-    async fn ready(&self, _: Context, ready: Ready) {
+    async fn ready(&self, ctx: Context, ready: Ready) {
         println!("{} connected at {}!", ready.user.name, Local::now());
+
+        if let Err(e) = register_slash_commands(&ctx.http).await {
+            println!("Error registering the slash commands: {:?}", e);
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::ApplicationCommand(command) = interaction {
+            run_slash_command(&ctx, &command).await;
+        }
     }
 }
 
+struct BotSettings;
+
+impl TypeMapKey for BotSettings {
+    type Value = Arc<Settings>;
+}
+
+struct WeatherSource;
+
+impl TypeMapKey for WeatherSource {
+    type Value = Arc<dyn WeatherProvider>;
+}
+
+struct GuildData;
+
+impl TypeMapKey for GuildData {
+    type Value = Arc<Mutex<GuildStore>>;
+}
+
 struct SongMap;
 
 impl TypeMapKey for SongMap {
-    type Value = Arc<Mutex<HashMap<String, PathBuf>>>;
+    type Value = Arc<Mutex<Packs>>;
+}
+
+struct AmbientSounds;
+
+impl TypeMapKey for AmbientSounds {
+    type Value = Arc<Ambience>;
 }
 
-struct SongCache;
+struct Sessions;
 
-impl TypeMapKey for SongCache {
-    type Value = Arc<Mutex<Vec<(String, Compressed)>>>;
+impl TypeMapKey for Sessions {
+    type Value = Arc<Mutex<HashMap<GuildId, Arc<Mutex<Session>>>>>;
 }
 
 struct WeatherCache;
 
 impl TypeMapKey for WeatherCache {
-    type Value = Arc<Mutex<WeatherData>>;
+    type Value = Arc<Mutex<HashMap<GuildId, Arc<Mutex<WeatherData>>>>>;
 }
 
-async fn get_key_current_hour(weather_cache: &mut WeatherData) -> String {
-    let hour = Local::now().hour();
-    let mut key = String::new();
+/// Gets the playback session of a guild, creating it the first time the guild plays.
+async fn guild_session(ctx: &Context, guild_id: GuildId) -> Arc<Mutex<Session>> {
+    let sessions_lock = ctx
+        .data
+        .read()
+        .await
+        .get::<Sessions>()
+        .cloned()
+        .expect("Sessions were installed at startup.");
+    let mut sessions = sessions_lock.lock().await;
+    sessions.entry(guild_id).or_default().clone()
+}
 
-    match get_weather(&LOCATION, API_KEY, weather_cache).await {
-        Ok(val) => match val {
-            Weather::Clear => key.push('0'),
-            Weather::Rainy => key.push('1'),
-            Weather::Snowy => key.push('2'),
-            Weather::Unknown => key.push('0'),
-        },
+/// Gets the weather data of a guild, creating it the first time the guild asks for weather.
+async fn guild_weather(ctx: &Context, guild_id: GuildId) -> Arc<Mutex<WeatherData>> {
+    let weather_cache_lock = ctx
+        .data
+        .read()
+        .await
+        .get::<WeatherCache>()
+        .cloned()
+        .expect("Weather cache was installed at startup.");
+    let mut weather_cache = weather_cache_lock.lock().await;
+    weather_cache.entry(guild_id).or_default().clone()
+}
+
+/// The name of the pack called `name`, or of the default pack, and a copy of it. The
+/// shared packs stay locked only for the lookup, so no guild holds them while it fetches
+/// the weather or reads a song.
+async fn guild_pack(packs: &Mutex<Packs>, name: Option<&str>) -> (String, Pack) {
+    let packs = packs.lock().await;
+    let (name, pack) = packs.get(name);
+    (name.to_string(), pack.clone())
+}
+
+/// Picks the key of the song to play for `weather` at the hour and season of `at` in
+/// `location`, substituting another song if the library has no file for that slot.
+/// `None` if the library is empty.
+fn song_key(
+    settings: &Settings,
+    songs: &HashMap<SongKey, Song>,
+    weather: Weather,
+    location: &Location,
+    at: DateTime<Tz>,
+) -> Option<SongKey> {
+    let fallbacks = settings.weather.fallback_chain(weather);
+    let season = Season::at(&at, location);
+    resolve(songs, weather, at.hour(), Some(season), &fallbacks).map(|(key, _)| key)
+}
+
+async fn get_key_current_hour(
+    settings: &Settings,
+    provider: &dyn WeatherProvider,
+    location: &Location,
+    tz: Tz,
+    songs: &HashMap<SongKey, Song>,
+    weather_cache: &mut WeatherData,
+) -> Option<SongKey> {
+    let weather = match get_weather(provider, location, &settings.weather, weather_cache).await {
+        Ok(val) => val,
         Err(e) => {
+            // Keep playing the last known weather until the API recovers.
             println!("Error fetching weather data: {}", e);
-            key.push('0') // default to clear
+            weather_cache.cached_weather
         }
     };
+    song_key(
+        settings,
+        songs,
+        weather,
+        location,
+        Utc::now().with_timezone(&tz),
+    )
+}
 
-    if hour < 10 {
-        key.push('0');
-        key.push_str(hour.to_string().as_str());
-    } else {
-        key.push_str(hour.to_string().as_str());
-    }
-    key
-}
-
-async fn get_key_next_hour(weather_cache: &mut WeatherData) -> String {
-    let get_key_next_hour = (Local::now() + Duration::hours(1))
-        .with_minute(0)
-        .unwrap()
-        .with_second(0)
-        .unwrap()
-        .with_nanosecond(0)
-        .unwrap()
-        .hour();
-    let mut key = String::new();
-
-    match get_weather(&LOCATION, API_KEY, weather_cache).await {
-        Ok(val) => match val {
-            Weather::Clear => key.push('0'),
-            Weather::Rainy => key.push('1'),
-            Weather::Snowy => key.push('2'),
-            Weather::Unknown => key.push('0'),
-        },
+async fn get_key_next_hour(
+    settings: &Settings,
+    provider: &dyn WeatherProvider,
+    location: &Location,
+    tz: Tz,
+    songs: &HashMap<SongKey, Song>,
+    weather_cache: &mut WeatherData,
+) -> Option<SongKey> {
+    let next_hour = start_of_next_hour(&Utc::now().with_timezone(&tz));
+
+    let forecast = get_forecast(
+        provider,
+        location,
+        next_hour.with_timezone(&Utc),
+        &settings.weather,
+        weather_cache,
+    )
+    .await;
+    let weather = match forecast {
+        Ok(val) => val,
         Err(e) => {
-            println!("Error fetching weather data: {}", e);
-            key.push('0') // default to clear
+            // Guess that the weather stays the same.
+            println!("Error fetching the forecast: {}", e);
+            match get_weather(provider, location, &settings.weather, weather_cache).await {
+                Ok(val) => val,
+                Err(_) => weather_cache.cached_weather,
+            }
         }
     };
+    song_key(settings, songs, weather, location, next_hour)
+}
 
-    if get_key_next_hour < 10 {
-        key.push('0');
-        key.push_str(get_key_next_hour.to_string().as_str());
-    } else {
-        key.push_str(get_key_next_hour.to_string().as_str());
-    }
-    key
+/// Time until just after the next hour starts in `tz`. The hourly event fired before the
+/// clock changed when it was due exactly on the hour, so half a second is added.
+fn time_to_next_hour(tz: Tz) -> std::time::Duration {
+    let now = Utc::now().with_timezone(&tz);
+    let next_hour = start_of_next_hour(&now) + Duration::milliseconds(500);
+    println!("next hour: {}", next_hour);
+    next_hour
+        .signed_duration_since(now)
+        .to_std()
+        .expect("The next hour is in the future.")
 }
 
-async fn compress_song(file_path: &PathBuf) -> Compressed {
-    let cached_song = Compressed::new(
-        input::ffmpeg(file_path)
-            .await
-            .expect("File not found in the songs folder."),
-        Bitrate::BitsPerSecond(128_000),
-    )
-    .expect("These parameters are well-defined.");
+/// Starts caching the song at `file_path`. Fails if ffmpeg cannot open it, which happens
+/// when a song is removed or still being copied before the library is rebuilt.
+async fn compress_song(file_path: &Path, bitrate: i32) -> Result<Compressed, String> {
+    let source = input::ffmpeg(file_path)
+        .await
+        .map_err(|e| format!("Could not open {}: {:?}", file_path.display(), e))?;
+    let cached_song = Compressed::new(source, Bitrate::BitsPerSecond(bitrate))
+        .map_err(|e| format!("Could not read {}: {:?}", file_path.display(), e))?;
     let _ = cached_song.raw.spawn_loader();
-    cached_song
+    Ok(cached_song)
+}
+
+/// The source that plays a cached song, repeating only its loop if it has `loop_points`.
+fn song_input(compressed: &Compressed, loop_points: Option<LoopPoints>) -> input::Input {
+    match loop_points {
+        Some(points) => intro_loop(compressed.clone().into(), points),
+        None => compressed.clone().into(),
+    }
 }
 
 #[group]
-#[commands(deafen, join, leave, mute, ping, undeafen, unmute, play, weather)]
+#[commands(
+    deafen,
+    join,
+    leave,
+    mute,
+    ping,
+    undeafen,
+    unmute,
+    play,
+    weather,
+    setlocation,
+    pack,
+    packs,
+    chime,
+    volume,
+    nowplaying,
+    timezone
+)]
 struct General;
 
-//Todo: Consider making a config file to allow the changing of directory name.
-const SONG_PATH: &str = "songs/";
-
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt::init();
 
+    let settings_path =
+        env::var("NOOKU_SETTINGS").unwrap_or_else(|_| String::from(DEFAULT_SETTINGS_PATH));
+    let settings =
+        Arc::new(Settings::load(Path::new(&settings_path)).expect("Error loading settings"));
+
+    // `nooku check` validates every pack and exits instead of starting the bot.
+    if env::args().nth(1).as_deref() == Some("check") {
+        let dirs = pack_dirs(&settings.songs).expect("Error finding the song packs");
+        let mut ok = true;
+        for (name, dir) in dirs {
+            println!("Pack {}", name);
+            ok &= check_library(&dir);
+            println!();
+        }
+        process::exit(if ok { 0 } else { 1 });
+    }
+
+    let guild_store =
+        GuildStore::load(Path::new(&settings.guild_data)).expect("Error loading guild settings");
+
     // Configure the client with your Discord bot token in the environment.
     let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
 
     let framework = StandardFramework::new()
-        .configure(|c| c.prefix("~"))
+        .configure(|c| c.prefix(settings.prefix.as_str()))
         .group(&GENERAL_GROUP);
 
-    let intents = GatewayIntents::non_privileged() | GatewayIntents::MESSAGE_CONTENT;
+    // Prefix commands need to read messages, slash commands work without it.
+    let intents = if settings.prefix_commands {
+        GatewayIntents::non_privileged() | GatewayIntents::MESSAGE_CONTENT
+    } else {
+        GatewayIntents::non_privileged()
+    };
 
     let mut client = Client::builder(&token, intents)
         .event_handler(Handler)
@@ -182,51 +357,36 @@ async fn main() {
     {
         let mut data = client.data.write().await;
 
-        let mut weather_cache = WeatherData {
-            last_call: Utc.ymd(1970, 1, 1).and_hms(0, 0, 0),
-            cached_weather: Weather::Clear,
-            playing_weather: Weather::Clear,
-        };
-
-        let mut song_map = HashMap::new();
-
-        for file in fs::read_dir(SONG_PATH).unwrap() {
-            let file_path = file.unwrap().path();
-            let file_path_str = file_path.display().to_string();
-            //Reads first 3 characters of file name containing the needed key
-            let file_key = &file_path_str[SONG_PATH.chars().count()..SONG_PATH.chars().count() + 3];
-            match file_key {
-                "REA" => {}
-                _ => {
-                    song_map.insert(String::from(file_key), file_path);
-                }
-            }
-        }
-
-        println!("{:?}", song_map);
-        println!("{} songs found in folder.", song_map.len());
+        let packs = Packs::load(&settings.songs).expect("Error reading the song packs");
+        println!("Default pack: {}", packs.default);
 
         println!(
             "Latitude: {}\nLongitude: {}",
-            LOCATION.latitude, LOCATION.longitude
+            settings.location.latitude, settings.location.longitude
         );
-
-        let mut song_cache = vec![];
-
-        let song_to_cache = get_key_current_hour(&mut weather_cache).await;
-
-        let cached_path = song_map.get(&song_to_cache).unwrap();
-        let cached_song = compress_song(cached_path).await;
-
-        song_cache.push((song_to_cache, cached_song));
-
-        //song_cache.push(compress_song(song_map.get(&songs_to_cache.1).unwrap()).await);
-
-        println!("Amount of cached songs {}", song_cache.len());
-
-        data.insert::<WeatherCache>(Arc::new(Mutex::new(weather_cache)));
-        data.insert::<SongMap>(Arc::new(Mutex::new(song_map)));
-        data.insert::<SongCache>(Arc::new(Mutex::new(song_cache)));
+        // Also loads the zone boundaries now rather than in the first guild's handler.
+        println!("Time zone: {}", zone_at(&settings.location));
+
+        data.insert::<BotSettings>(settings.clone());
+        data.insert::<WeatherSource>(Arc::from(provider_from_settings(&settings.weather)));
+        data.insert::<GuildData>(Arc::new(Mutex::new(guild_store)));
+        data.insert::<WeatherCache>(Arc::new(Mutex::new(HashMap::new())));
+        data.insert::<AmbientSounds>(Arc::new(Ambience::load(&settings.ambience)));
+        let packs = Arc::new(Mutex::new(packs));
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+        match SongWatcher::new(Path::new(settings.songs.root())) {
+            Ok(watcher) => {
+                tokio::spawn(reload_songs(
+                    watcher,
+                    settings.clone(),
+                    packs.clone(),
+                    sessions.clone(),
+                ));
+            }
+            Err(e) => println!("Not watching {} for changes: {}", settings.songs.root(), e),
+        }
+        data.insert::<SongMap>(packs);
+        data.insert::<Sessions>(sessions);
     }
 
     let _ = client
@@ -235,24 +395,82 @@ async fn main() {
         .map_err(|why| println!("Client ended: {:?}", why));
 }
 
+/// Rebuilds the song packs whenever files in them change. Guilds keep playing their
+/// current track and pick up a changed file at its next loop.
+async fn reload_songs(
+    mut watcher: SongWatcher,
+    settings: Arc<Settings>,
+    packs: Arc<Mutex<Packs>>,
+    sessions: Arc<Mutex<HashMap<GuildId, Arc<Mutex<Session>>>>>,
+) {
+    let no_songs = HashMap::new();
+    while let Some(changed_files) = watcher.changed().await {
+        // Reading the loop tags runs ffprobe on every song of the changed packs, which blocks.
+        let load_settings = settings.clone();
+        let old_packs = packs.lock().await.clone();
+        let load_files = changed_files.clone();
+        let loaded = tokio::task::spawn_blocking(move || {
+            old_packs.reload(&load_settings.songs, &load_files)
+        })
+        .await;
+        let new_packs = match loaded.map_err(|e| e.to_string()).and_then(|loaded| loaded) {
+            Ok(new_packs) => new_packs,
+            Err(e) => {
+                println!("Could not reload the song packs: {}", e);
+                continue;
+            }
+        };
+
+        let mut changed_packs = Vec::new();
+        {
+            let mut packs = packs.lock().await;
+            let names: BTreeSet<&String> =
+                packs.packs.keys().chain(new_packs.packs.keys()).collect();
+            for name in names {
+                let old_songs = packs.packs.get(name).map_or(&no_songs, |pack| &*pack.songs);
+                let new_songs = new_packs
+                    .packs
+                    .get(name)
+                    .map_or(&no_songs, |pack| &*pack.songs);
+                let changed = changed_keys(old_songs, new_songs, &changed_files);
+                if !changed.is_empty() {
+                    println!("Pack {} changed: {} songs changed.", name, changed.len());
+                    changed_packs.push((name.clone(), changed));
+                }
+            }
+            *packs = new_packs;
+        }
+
+        // A guild's session can stay locked while it fetches the weather, so the packs are
+        // let go of first.
+        let sessions: Vec<_> = sessions.lock().await.values().cloned().collect();
+        for (name, changed) in &changed_packs {
+            for session in &sessions {
+                session.lock().await.invalidate(name, changed);
+            }
+        }
+    }
+}
+
 #[command]
 #[only_in(guilds)]
 async fn play(ctx: &Context, msg: &Message) -> CommandResult {
-    let guild = msg.guild(&ctx.cache).unwrap();
-    let guild_id = guild.id;
-
-    let channel_id = guild
-        .voice_states
-        .get(&msg.author.id)
-        .and_then(|voice_state| voice_state.channel_id);
+    let reply = start_playing(ctx, msg.guild_id.unwrap(), msg.author.id, msg.channel_id).await;
+    send_reply(ctx, msg, reply).await;
+    Ok(())
+}
 
-    let connect_to = match channel_id {
+/// Joins the voice channel `user_id` is in and starts playing, announcing every new hour
+/// in `chan_id`.
+async fn start_playing(
+    ctx: &Context,
+    guild_id: GuildId,
+    user_id: UserId,
+    chan_id: ChannelId,
+) -> Reply {
+    let connect_to = match user_voice_channel(ctx, guild_id, user_id) {
         Some(channel) => channel,
-        None => {
-            check_msg(msg.reply(ctx, "Not in a voice channel").await);
-
-            return Ok(());
-        }
+        None => return Err(String::from("Not in a voice channel")),
     };
 
     let manager = songbird::get(ctx)
@@ -261,14 +479,12 @@ async fn play(ctx: &Context, msg: &Message) -> CommandResult {
         .clone();
 
     //Gets the currently connected channel ID to disallow multiple calls from ~play. This prevents multiple Events from being registered.
-    let manager_call = manager.get(guild_id);
-    if manager_call.is_some() {
-        let current_call_id = manager_call.unwrap().lock().await.current_channel();
+    if let Some(manager_call) = manager.get(guild_id) {
+        let current_call_id = manager_call.lock().await.current_channel();
         if current_call_id.is_some()
             && current_call_id.unwrap().to_string() == connect_to.to_string()
         {
-            check_msg(msg.reply(ctx, "Already in same voice channel!").await);
-            return Ok(());
+            return Err(String::from("Already in same voice channel!"));
         }
     }
 
@@ -278,30 +494,6 @@ async fn play(ctx: &Context, msg: &Message) -> CommandResult {
     let call_lock_for_track_evt = Arc::downgrade(&handler_lock);
 
     if let Ok(_reader) = success_reader {
-        let mut handler = handler_lock.lock().await;
-        check_msg(
-            msg.channel_id
-                .say(
-                    &ctx.http,
-                    &format!(
-                        "Joined {} <t:{}:R>.",
-                        connect_to.mention(),
-                        Utc::now().timestamp()
-                    ),
-                )
-                .await,
-        );
-
-        let vec_sources_lock = ctx
-            .data
-            .read()
-            .await
-            .get::<SongCache>()
-            .cloned()
-            .expect("Sound cache was installed at startup.");
-        let vec_sources_lock_for_evt = vec_sources_lock.clone();
-        let mut vec_sources = vec_sources_lock.lock().await;
-
         let hash_sources_lock = ctx
             .data
             .read()
@@ -311,69 +503,102 @@ async fn play(ctx: &Context, msg: &Message) -> CommandResult {
             .expect("Sound cache was installed at startup.");
         let hash_sources_lock_for_global_evt = hash_sources_lock.clone();
         let hash_sources_lock_for_track_evt = hash_sources_lock.clone();
-        let hash_sources = hash_sources_lock.lock().await;
-        let hash_source = hash_sources;
 
-        let weather_cache_lock = ctx
+        let session_lock = guild_session(ctx, guild_id).await;
+        let mut session = session_lock.lock().await;
+
+        let weather_cache_lock = guild_weather(ctx, guild_id).await;
+        let weather_cache_lock_for_global_evt = weather_cache_lock.clone();
+        let weather_cache_lock_for_track_evt = weather_cache_lock.clone();
+        let mut weather_cache = weather_cache_lock.lock().await;
+
+        let settings = ctx
             .data
             .read()
             .await
-            .get::<WeatherCache>()
+            .get::<BotSettings>()
             .cloned()
-            .expect("Weather cache was installed at startup.");
-        let weather_cache_lock_for_global_evt = weather_cache_lock.clone();
-        let weather_cache_lock_for_track_evt = weather_cache_lock.clone();
-        let mut weather_cache = weather_cache_lock.lock().await;
+            .expect("Settings were installed at startup.");
+        let provider = ctx
+            .data
+            .read()
+            .await
+            .get::<WeatherSource>()
+            .cloned()
+            .expect("Weather provider was installed at startup.");
 
-        let mut vec_source = vec_sources.remove(0);
-        let key = get_key_current_hour(&mut weather_cache).await;
+        let ambience = ctx
+            .data
+            .read()
+            .await
+            .get::<AmbientSounds>()
+            .cloned()
+            .expect("Ambience was installed at startup.");
 
-        if vec_source.0 != key {
-            if vec_sources.len() > 0 {
-                vec_sources.remove(0);
-            }
-            let this_hour_compressed = compress_song(hash_source.get(&key).unwrap()).await;
-            vec_source = (key, this_hour_compressed);
-        }
-        let source_clone = vec_source.1.clone();
-        let song = handler.play_only_source(source_clone.into());
-        let _ = song.set_volume(1.0);
-        let _ = song.enable_loop();
+        let guilds = ctx
+            .data
+            .read()
+            .await
+            .get::<GuildData>()
+            .cloned()
+            .expect("Guild settings were installed at startup.");
+        let location = guilds.lock().await.location(guild_id.0, &settings.location);
+        let tz = guilds.lock().await.timezone(guild_id.0, &settings.location);
+        let pack = guilds.lock().await.pack(guild_id.0);
+        let guild_volume = guilds.lock().await.volume(guild_id.0);
+        let (pack_name, pack) = guild_pack(&hash_sources_lock, pack.as_deref()).await;
+        let hash_source = &pack.songs;
+
+        let key = match get_key_current_hour(
+            &settings,
+            &*provider,
+            &location,
+            tz,
+            hash_source,
+            &mut weather_cache,
+        )
+        .await
+        {
+            Some(key) => key,
+            None => return Err(String::from("No songs found in the songs folder")),
+        };
 
-        //vec_sources.insert(0, vec_source);
+        let this_hour_compressed = match session.cached(key) {
+            Some(cached) => cached,
+            None => match compress_song(&hash_source[&key].path, settings.songs.bitrate).await {
+                Ok(compressed) => compressed,
+                Err(e) => {
+                    println!("{}", e);
+                    return Err(format!("Could not play {}", hash_source[&key].title()));
+                }
+            },
+        };
 
-        if vec_sources.len() == 0 {
-            let next_hour_key = get_key_next_hour(&mut weather_cache).await;
-            let next_hour_compressed =
-                compress_song(hash_source.get(&next_hour_key).unwrap()).await;
-            vec_sources.push((next_hour_key, next_hour_compressed));
-        }
+        // Locked after the session and the weather, like the event handlers do, and only
+        // once the song is ready, so the call is not held while fetching the weather.
+        let mut handler = handler_lock.lock().await;
 
-        let chan_id = msg.channel_id;
+        let loop_points = hash_source[&key].loop_points(this_hour_compressed.metadata.duration);
+        let song = start_track(
+            &mut handler,
+            song_input(&this_hour_compressed, loop_points),
+            hash_source[&key].volume() * guild_volume,
+            &mut session,
+            settings.songs.crossfade(),
+        );
+        session.set_current(
+            &pack_name,
+            key,
+            this_hour_compressed,
+            song.clone(),
+            hash_source[&key].volume(),
+        );
+        weather_cache.playing_weather = weather_cache.cached_weather;
 
         let send_http = ctx.http.clone();
 
-        //Errors would occur from the event firing before local time changed. 1/2 second added to try to prevent this.
-        let key_next_hour = (Local::now() + Duration::hours(1))
-            .with_minute(0)
-            .unwrap()
-            .with_second(0)
-            .unwrap()
-            .with_nanosecond(500000000)
-            .unwrap();
-
-        let time_to_top_hour = key_next_hour
-            .signed_duration_since(Local::now())
-            .to_std()
-            .unwrap();
-
-        println!(
-            "next hour: {} \ntime to next hour: {:?}",
-            key_next_hour, time_to_top_hour
-        );
-
-        println!("cache contents: {:?}", vec_sources);
-        println!("cache size: {:?}", vec_sources.len());
+        let time_to_top_hour = time_to_next_hour(tz);
+        println!("time to next hour: {:?}", time_to_top_hour);
 
         //removes all global events before adding the hourly global event. REMOVE THIS IF USING MORE THAN JUST THIS GLOBAL EVENT!!!
         handler.remove_all_global_events();
@@ -386,74 +611,225 @@ async fn play(ctx: &Context, msg: &Message) -> CommandResult {
             // ),
             HourChange {
                 chan_id,
+                guild_id,
                 http: send_http,
                 call_lock: call_lock_for_global_evt,
-                vec_sources: vec_sources_lock_for_evt,
+                session: session_lock.clone(),
                 hash_sources: hash_sources_lock_for_global_evt,
                 weather_cache: weather_cache_lock_for_global_evt,
+                guilds: guilds.clone(),
+                ambience: ambience.clone(),
+                provider: provider.clone(),
+                settings: settings.clone(),
             },
         );
-        let _ = song.add_event(
-            Event::Track(TrackEvent::Loop),
+        loop_song(
+            &song,
+            loop_points,
             CheckWeather {
+                guild_id,
                 call_lock: call_lock_for_track_evt,
+                session: session_lock.clone(),
                 hash_sources: hash_sources_lock_for_track_evt,
                 weather_cache: weather_cache_lock_for_track_evt,
+                guilds,
+                ambience: ambience.clone(),
+                provider: provider.clone(),
+                settings: settings.clone(),
             },
         );
+        drop(handler);
+
+        let next_hour_key = get_key_next_hour(
+            &settings,
+            &*provider,
+            &location,
+            tz,
+            hash_source,
+            &mut weather_cache,
+        )
+        .await;
+        if let Some(next_hour_key) = next_hour_key {
+            if session.needs_prefetch(next_hour_key) {
+                match compress_song(&hash_source[&next_hour_key].path, settings.songs.bitrate).await
+                {
+                    Ok(compressed) => session.next = Some((next_hour_key, compressed)),
+                    Err(e) => println!("Not prefetching the next hour's song: {}", e),
+                }
+            }
+        }
+
+        println!("current song: {:?}", session.current);
+        println!("prefetched song: {:?}", session.next);
+
+        ambience
+            .update(
+                &handler_lock,
+                &mut session,
+                weather_cache.cached_weather,
+                weather_cache.cached_intensity,
+                &settings.weather,
+                guild_volume,
+            )
+            .await;
+
+        Ok(format!(
+            "Joined {} <t:{}:R>.",
+            connect_to.mention(),
+            Utc::now().timestamp()
+        ))
     } else {
-        check_msg(
-            msg.channel_id
-                .say(&ctx.http, "Error joining the channel")
-                .await,
-        );
+        Err(String::from("Error joining the channel"))
     }
-
-    Ok(())
 }
 
 struct CheckWeather {
+    guild_id: GuildId,
     call_lock: Weak<Mutex<Call>>,
-    hash_sources: Arc<Mutex<HashMap<String, PathBuf>>>,
+    session: Arc<Mutex<Session>>,
+    hash_sources: Arc<Mutex<Packs>>,
     weather_cache: Arc<Mutex<WeatherData>>,
+    guilds: Arc<Mutex<GuildStore>>,
+    ambience: Arc<Ambience>,
+    provider: Arc<dyn WeatherProvider>,
+    settings: Arc<Settings>,
+}
+
+/// Loops `song` and checks the weather at every loop boundary. With loop points the song's
+/// source repeats the loop by itself, see [`song_input`], otherwise the whole file repeats.
+fn loop_song(song: &TrackHandle, loop_points: Option<LoopPoints>, check: CheckWeather) {
+    let _ = match loop_points.map(frame_aligned) {
+        Some(points) => song.add_event(
+            Event::Periodic(points.length, Some(points.start + points.length)),
+            check,
+        ),
+        None => {
+            let _ = song.enable_loop();
+            song.add_event(Event::Track(TrackEvent::Loop), check)
+        }
+    };
 }
 
 #[async_trait]
 impl VoiceEventHandler for CheckWeather {
     async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
-        let mut weather_data = self.weather_cache.lock().await;
-        let key_check = get_key_current_hour(&mut weather_data).await;
-        if weather_data.cached_weather != weather_data.playing_weather {
-            println!(
-                "Old weather: {:?}\nNew weather: {:?}\nKey for current hour: {}",
-                weather_data.playing_weather, weather_data.cached_weather, key_check
-            );
-            weather_data.playing_weather = match weather_data.cached_weather {
-                Weather::Clear => Weather::Clear,
-                Weather::Rainy => Weather::Rainy,
-                Weather::Snowy => Weather::Snowy,
-                Weather::Unknown => Weather::Unknown,
+        let location = self
+            .guilds
+            .lock()
+            .await
+            .location(self.guild_id.0, &self.settings.location);
+        let tz = self
+            .guilds
+            .lock()
+            .await
+            .timezone(self.guild_id.0, &self.settings.location);
+        let pack = self.guilds.lock().await.pack(self.guild_id.0);
+        let guild_volume = self.guilds.lock().await.volume(self.guild_id.0);
+
+        // Takes what it needs from the guild's weather, then lets go of it before waiting on
+        // the call. The shared packs are only locked to copy the guild's pack.
+        let (pack_name, pack) = guild_pack(&self.hash_sources, pack.as_deref()).await;
+        let (key_check, song, weather, intensity, weather_changed) = {
+            let hash_source = &pack.songs;
+            let mut weather_data = self.weather_cache.lock().await;
+            let key_check = match get_key_current_hour(
+                &self.settings,
+                &*self.provider,
+                &location,
+                tz,
+                hash_source,
+                &mut weather_data,
+            )
+            .await
+            {
+                Some(key) => key,
+                None => {
+                    println!("No songs found in the songs folder.");
+                    return None;
+                }
             };
-            if let Some(call_lock) = self.call_lock.upgrade() {
-                let hash_source = self.hash_sources.lock().await;
-
-                let current_hour_compressed =
-                    compress_song(hash_source.get(&key_check).unwrap()).await;
-
-                let mut handler = call_lock.lock().await;
-                let song = handler.play_only_source(current_hour_compressed.into());
-                let _ = song.set_volume(1.0);
-                let _ = song.enable_loop();
-
-                let _ = song.add_event(
-                    Event::Track(TrackEvent::Loop),
-                    CheckWeather {
-                        call_lock: self.call_lock.clone(),
-                        hash_sources: self.hash_sources.clone(),
-                        weather_cache: self.weather_cache.clone(),
-                    },
+            let weather_changed = weather_data.cached_weather != weather_data.playing_weather;
+            if weather_changed {
+                println!(
+                    "Old weather: {:?}\nNew weather: {:?}\nKey for current hour: {}",
+                    weather_data.playing_weather, weather_data.cached_weather, key_check
                 );
+                weather_data.playing_weather = weather_data.cached_weather;
             }
+            (
+                key_check,
+                hash_source[&key_check].clone(),
+                weather_data.cached_weather,
+                weather_data.cached_intensity,
+                weather_changed,
+            )
+        };
+
+        let call_lock = self.call_lock.upgrade()?;
+        let mut session = self.session.lock().await;
+        session.loops += 1;
+        // The intensity can change without the weather changing.
+        self.ambience
+            .update(
+                &call_lock,
+                &mut session,
+                weather,
+                intensity,
+                &self.settings.weather,
+                guild_volume,
+            )
+            .await;
+        if weather_changed || session.reload {
+            if session.current.as_ref().map(|(key, _)| *key) == Some(key_check) {
+                // The new weather falls back to the song that is already playing.
+                return None;
+            }
+
+            let current_hour_compressed = match session.cached(key_check) {
+                Some(cached) => cached,
+                None => match compress_song(&song.path, self.settings.songs.bitrate).await {
+                    Ok(compressed) => compressed,
+                    Err(e) => {
+                        // Keeps the song that is playing until the next loop tries again.
+                        println!("{}", e);
+                        session.reload = true;
+                        return None;
+                    }
+                },
+            };
+
+            let mut handler = call_lock.lock().await;
+            let loop_points = song.loop_points(current_hour_compressed.metadata.duration);
+            let track = start_track(
+                &mut handler,
+                song_input(&current_hour_compressed, loop_points),
+                song.volume() * guild_volume,
+                &mut session,
+                self.settings.songs.crossfade(),
+            );
+            session.set_current(
+                &pack_name,
+                key_check,
+                current_hour_compressed,
+                track.clone(),
+                song.volume(),
+            );
+
+            loop_song(
+                &track,
+                loop_points,
+                CheckWeather {
+                    guild_id: self.guild_id,
+                    call_lock: self.call_lock.clone(),
+                    session: self.session.clone(),
+                    hash_sources: self.hash_sources.clone(),
+                    weather_cache: self.weather_cache.clone(),
+                    guilds: self.guilds.clone(),
+                    ambience: self.ambience.clone(),
+                    provider: self.provider.clone(),
+                    settings: self.settings.clone(),
+                },
+            );
         }
         None
     }
@@ -461,16 +837,36 @@ impl VoiceEventHandler for CheckWeather {
 
 struct HourChange {
     chan_id: ChannelId,
+    guild_id: GuildId,
     http: Arc<Http>,
     call_lock: Weak<Mutex<Call>>,
-    vec_sources: Arc<Mutex<Vec<(String, Compressed)>>>,
-    hash_sources: Arc<Mutex<HashMap<String, PathBuf>>>,
+    session: Arc<Mutex<Session>>,
+    hash_sources: Arc<Mutex<Packs>>,
     weather_cache: Arc<Mutex<WeatherData>>,
+    guilds: Arc<Mutex<GuildStore>>,
+    ambience: Arc<Ambience>,
+    provider: Arc<dyn WeatherProvider>,
+    settings: Arc<Settings>,
 }
 
 #[async_trait]
 impl VoiceEventHandler for HourChange {
     async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let tz = self
+            .guilds
+            .lock()
+            .await
+            .timezone(self.guild_id.0, &self.settings.location);
+        // Songbird schedules the next firing from when this one started, not from when it
+        // returns, so the time to the next hour is taken before anything slow runs.
+        let time_to_top_hour = time_to_next_hour(tz);
+        let period = Duration::hours(1).to_std().unwrap();
+        if time_to_top_hour + std::time::Duration::from_secs(60) < period {
+            // Fired well past the top of the hour, which the guild's new time zone moved
+            // since the timer was set, so the timer is only lined up with it again.
+            return Some(Event::Periodic(period, Some(time_to_top_hour)));
+        }
+
         check_msg(
             self.chan_id
                 .say(
@@ -481,67 +877,162 @@ impl VoiceEventHandler for HourChange {
         );
 
         if let Some(call_lock) = self.call_lock.upgrade() {
-            let hash_source = self.hash_sources.lock().await;
+            let (pack, chime_on, guild_volume) = {
+                let guilds = self.guilds.lock().await;
+                (
+                    guilds.pack(self.guild_id.0),
+                    guilds.chime(self.guild_id.0),
+                    guilds.volume(self.guild_id.0),
+                )
+            };
+            let (pack_name, pack) = guild_pack(&self.hash_sources, pack.as_deref()).await;
+            let hash_source = &pack.songs;
 
-            let mut vec_sources = self.vec_sources.lock().await;
+            let mut session = self.session.lock().await;
 
             let mut weather_data = self.weather_cache.lock().await;
 
-            let mut src = vec_sources.remove(0);
+            let location = self
+                .guilds
+                .lock()
+                .await
+                .location(self.guild_id.0, &self.settings.location);
+
+            let current_hour_key = match get_key_current_hour(
+                &self.settings,
+                &*self.provider,
+                &location,
+                tz,
+                hash_source,
+                &mut weather_data,
+            )
+            .await
+            {
+                Some(key) => key,
+                None => {
+                    println!("No songs found in the songs folder.");
+                    return None;
+                }
+            };
 
-            let current_hour_key = get_key_current_hour(&mut weather_data).await;
+            println!(
+                "Current hour key: {} ({})",
+                current_hour_key,
+                hash_source[&current_hour_key].title()
+            );
 
-            println!("Current hour key: {}", current_hour_key);
+            let current_hour_compressed = match session.cached(current_hour_key) {
+                Some(cached) => cached,
+                None => {
+                    let path = &hash_source[&current_hour_key].path;
+                    match compress_song(path, self.settings.songs.bitrate).await {
+                        Ok(compressed) => compressed,
+                        Err(e) => {
+                            // Keeps the old hour's song playing until its next loop.
+                            println!("{}", e);
+                            session.reload = true;
+                            return None;
+                        }
+                    }
+                }
+            };
 
-            if current_hour_key != src.0 {
-                let current_hour_compressed =
-                    compress_song(hash_source.get(&current_hour_key).unwrap()).await;
-                src = (current_hour_key, current_hour_compressed);
+            // The old hour's song only fades out under the chime once the new one is ready.
+            if chime_on {
+                if let Some(chime) = &pack.chime {
+                    play_chime(&call_lock, &mut session, chime, guild_volume).await;
+                }
             }
 
             let mut handler = call_lock.lock().await;
-            let src_clone = src.1.clone();
-            let song = handler.play_only_source(src_clone.into());
-            let _ = song.set_volume(1.0);
-            let _ = song.enable_loop();
+            let loop_points = hash_source[&current_hour_key]
+                .loop_points(current_hour_compressed.metadata.duration);
+            let song = start_track(
+                &mut handler,
+                song_input(&current_hour_compressed, loop_points),
+                hash_source[&current_hour_key].volume() * guild_volume,
+                &mut session,
+                self.settings.songs.crossfade(),
+            );
+            session.set_current(
+                &pack_name,
+                current_hour_key,
+                current_hour_compressed,
+                song.clone(),
+                hash_source[&current_hour_key].volume(),
+            );
 
-            weather_data.playing_weather = match weather_data.cached_weather {
-                Weather::Clear => Weather::Clear,
-                Weather::Rainy => Weather::Rainy,
-                Weather::Snowy => Weather::Snowy,
-                Weather::Unknown => Weather::Unknown,
-            };
+            weather_data.playing_weather = weather_data.cached_weather;
+            drop(handler);
+            self.ambience
+                .update(
+                    &call_lock,
+                    &mut session,
+                    weather_data.cached_weather,
+                    weather_data.cached_intensity,
+                    &self.settings.weather,
+                    guild_volume,
+                )
+                .await;
 
-            let _ = song.add_event(
-                Event::Track(TrackEvent::Loop),
+            loop_song(
+                &song,
+                loop_points,
                 CheckWeather {
+                    guild_id: self.guild_id,
                     call_lock: self.call_lock.clone(),
+                    session: self.session.clone(),
                     hash_sources: self.hash_sources.clone(),
                     weather_cache: self.weather_cache.clone(),
+                    guilds: self.guilds.clone(),
+                    ambience: self.ambience.clone(),
+                    provider: self.provider.clone(),
+                    settings: self.settings.clone(),
                 },
             );
 
-            if vec_sources.len() == 0 {
-                let next_hour_key = get_key_next_hour(&mut weather_data).await;
-                let next_hour_compressed =
-                    compress_song(hash_source.get(&next_hour_key).unwrap()).await;
-                vec_sources.push((next_hour_key, next_hour_compressed));
+            let next_hour_key = get_key_next_hour(
+                &self.settings,
+                &*self.provider,
+                &location,
+                tz,
+                hash_source,
+                &mut weather_data,
+            )
+            .await;
+            if let Some(next_hour_key) = next_hour_key {
+                if session.needs_prefetch(next_hour_key) {
+                    let path = &hash_source[&next_hour_key].path;
+                    match compress_song(path, self.settings.songs.bitrate).await {
+                        Ok(compressed) => session.next = Some((next_hour_key, compressed)),
+                        Err(e) => println!("Not prefetching the next hour's song: {}", e),
+                    }
+                }
             }
 
-            println!("cache contents: {:?}", vec_sources);
-            println!("cache size: {:?}", vec_sources.len());
+            println!("current song: {:?}", session.current);
+            println!("prefetched song: {:?}", session.next);
         }
 
-        None
+        // Keeps firing every hour unless the timer drifted off the top of the hour, in
+        // which case it is lined up again.
+        if period.abs_diff(time_to_top_hour) < std::time::Duration::from_secs(1) {
+            None
+        } else {
+            Some(Event::Periodic(period, Some(time_to_top_hour)))
+        }
     }
 }
 
 #[command]
 #[only_in(guilds)]
 async fn deafen(ctx: &Context, msg: &Message) -> CommandResult {
-    let guild = msg.guild(&ctx.cache).unwrap();
-    let guild_id = guild.id;
+    send_reply(ctx, msg, set_deaf(ctx, msg.guild_id.unwrap(), true).await).await;
+    Ok(())
+}
 
+/// Deafens or undeafens the bot in the guild's call.
+async fn set_deaf(ctx: &Context, guild_id: GuildId, deaf: bool) -> Reply {
     let manager = songbird::get(ctx)
         .await
         .expect("Songbird Voice client placed in at initialisation.")
@@ -549,50 +1040,38 @@ async fn deafen(ctx: &Context, msg: &Message) -> CommandResult {
 
     let handler_lock = match manager.get(guild_id) {
         Some(handler) => handler,
-        None => {
-            check_msg(msg.reply(ctx, "Not in a voice channel").await);
-
-            return Ok(());
-        }
+        None => return Err(String::from("Not in a voice channel")),
     };
 
     let mut handler = handler_lock.lock().await;
 
-    if handler.is_deaf() {
-        check_msg(msg.channel_id.say(&ctx.http, "Already deafened").await);
-    } else {
-        if let Err(e) = handler.deafen(true).await {
-            check_msg(
-                msg.channel_id
-                    .say(&ctx.http, format!("Failed: {:?}", e))
-                    .await,
-            );
-        }
-
-        check_msg(msg.channel_id.say(&ctx.http, "Deafened").await);
+    if handler.is_deaf() == deaf {
+        return Err(String::from(if deaf {
+            "Already deafened"
+        } else {
+            "Not deafened"
+        }));
+    }
+    if let Err(e) = handler.deafen(deaf).await {
+        return Err(format!("Failed: {:?}", e));
     }
 
-    Ok(())
+    Ok(String::from(if deaf { "Deafened" } else { "Undeafened" }))
 }
 
 #[command]
 #[only_in(guilds)]
 async fn join(ctx: &Context, msg: &Message) -> CommandResult {
-    let guild = msg.guild(&ctx.cache).unwrap();
-    let guild_id = guild.id;
-
-    let channel_id = guild
-        .voice_states
-        .get(&msg.author.id)
-        .and_then(|voice_state| voice_state.channel_id);
+    let reply = join_channel(ctx, msg.guild_id.unwrap(), msg.author.id).await;
+    send_reply(ctx, msg, reply).await;
+    Ok(())
+}
 
-    let connect_to = match channel_id {
+/// Joins the voice channel `user_id` is in without playing anything.
+async fn join_channel(ctx: &Context, guild_id: GuildId, user_id: UserId) -> Reply {
+    let connect_to = match user_voice_channel(ctx, guild_id, user_id) {
         Some(channel) => channel,
-        None => {
-            check_msg(msg.reply(ctx, "Not in a voice channel").await);
-
-            return Ok(());
-        }
+        None => return Err(String::from("Not in a voice channel")),
     };
 
     let manager = songbird::get(ctx)
@@ -600,46 +1079,55 @@ async fn join(ctx: &Context, msg: &Message) -> CommandResult {
         .expect("Songbird Voice client placed in at initialisation.")
         .clone();
 
-    let _handler = manager.join(guild_id, connect_to).await;
-
-    Ok(())
+    match manager.join(guild_id, connect_to).await.1 {
+        Ok(()) => Ok(format!("Joined {}", connect_to.mention())),
+        Err(_) => Err(String::from("Error joining the channel")),
+    }
 }
 
 #[command]
 #[only_in(guilds)]
 async fn leave(ctx: &Context, msg: &Message) -> CommandResult {
-    let guild = msg.guild(&ctx.cache).unwrap();
-    let guild_id = guild.id;
+    send_reply(ctx, msg, leave_channel(ctx, msg.guild_id.unwrap()).await).await;
+    Ok(())
+}
 
+/// Leaves the guild's voice channel and drops its playback session.
+async fn leave_channel(ctx: &Context, guild_id: GuildId) -> Reply {
     let manager = songbird::get(ctx)
         .await
         .expect("Songbird Voice client placed in at initialisation.")
         .clone();
-    let has_handler = manager.get(guild_id).is_some();
-
-    if has_handler {
-        if let Err(e) = manager.remove(guild_id).await {
-            check_msg(
-                msg.channel_id
-                    .say(&ctx.http, format!("Failed: {:?}", e))
-                    .await,
-            );
-        }
+    if manager.get(guild_id).is_none() {
+        return Err(String::from("Not in a voice channel"));
+    }
 
-        check_msg(msg.channel_id.say(&ctx.http, "Left voice channel").await);
-    } else {
-        check_msg(msg.reply(ctx, "Not in a voice channel").await);
+    ctx.data
+        .read()
+        .await
+        .get::<Sessions>()
+        .cloned()
+        .expect("Sessions were installed at startup.")
+        .lock()
+        .await
+        .remove(&guild_id);
+
+    if let Err(e) = manager.remove(guild_id).await {
+        return Err(format!("Failed: {:?}", e));
     }
 
-    Ok(())
+    Ok(String::from("Left voice channel"))
 }
 
 #[command]
 #[only_in(guilds)]
 async fn mute(ctx: &Context, msg: &Message) -> CommandResult {
-    let guild = msg.guild(&ctx.cache).unwrap();
-    let guild_id = guild.id;
+    send_reply(ctx, msg, set_mute(ctx, msg.guild_id.unwrap(), true).await).await;
+    Ok(())
+}
 
+/// Mutes or unmutes the bot in the guild's call.
+async fn set_mute(ctx: &Context, guild_id: GuildId, mute: bool) -> Reply {
     let manager = songbird::get(ctx)
         .await
         .expect("Songbird Voice client placed in at initialisation.")
@@ -647,30 +1135,23 @@ async fn mute(ctx: &Context, msg: &Message) -> CommandResult {
 
     let handler_lock = match manager.get(guild_id) {
         Some(handler) => handler,
-        None => {
-            check_msg(msg.reply(ctx, "Not in a voice channel").await);
-
-            return Ok(());
-        }
+        None => return Err(String::from("Not in a voice channel")),
     };
 
     let mut handler = handler_lock.lock().await;
 
-    if handler.is_mute() {
-        check_msg(msg.channel_id.say(&ctx.http, "Already muted").await);
-    } else {
-        if let Err(e) = handler.mute(true).await {
-            check_msg(
-                msg.channel_id
-                    .say(&ctx.http, format!("Failed: {:?}", e))
-                    .await,
-            );
-        }
-
-        check_msg(msg.channel_id.say(&ctx.http, "Now muted").await);
+    if handler.is_mute() == mute {
+        return Err(String::from(if mute {
+            "Already muted"
+        } else {
+            "Not muted"
+        }));
+    }
+    if let Err(e) = handler.mute(mute).await {
+        return Err(format!("Failed: {:?}", e));
     }
 
-    Ok(())
+    Ok(String::from(if mute { "Now muted" } else { "Unmuted" }))
 }
 
 #[command]
@@ -683,94 +1164,749 @@ async fn ping(context: &Context, msg: &Message) -> CommandResult {
 #[command]
 #[only_in(guilds)]
 async fn undeafen(ctx: &Context, msg: &Message) -> CommandResult {
-    let guild = msg.guild(&ctx.cache).unwrap();
-    let guild_id = guild.id;
+    send_reply(ctx, msg, set_deaf(ctx, msg.guild_id.unwrap(), false).await).await;
+    Ok(())
+}
 
-    let manager = songbird::get(ctx)
+#[command]
+#[only_in(guilds)]
+async fn weather(ctx: &Context, msg: &Message) -> CommandResult {
+    send_reply(ctx, msg, current_weather(ctx, msg.guild_id.unwrap()).await).await;
+    Ok(())
+}
+
+/// The weather at the guild's location.
+async fn current_weather(ctx: &Context, guild_id: GuildId) -> Reply {
+    let weather_cache_lock = guild_weather(ctx, guild_id).await;
+    let settings = ctx
+        .data
+        .read()
         .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
+        .get::<BotSettings>()
+        .cloned()
+        .expect("Settings were installed at startup.");
+    let provider = ctx
+        .data
+        .read()
+        .await
+        .get::<WeatherSource>()
+        .cloned()
+        .expect("Weather provider was installed at startup.");
+    let location = ctx
+        .data
+        .read()
+        .await
+        .get::<GuildData>()
+        .cloned()
+        .expect("Guild settings were installed at startup.")
+        .lock()
+        .await
+        .location(guild_id.0, &settings.location);
+    let mut weather_data = weather_cache_lock.lock().await;
+    match get_weather(&*provider, &location, &settings.weather, &mut weather_data).await {
+        Ok(weather) => Ok(format!("{:?}", weather)),
+        Err(e) => {
+            println!("Error fetching weather data: {}", e);
+            Err(format!("Could not fetch the weather: {}", e))
+        }
+    }
+}
 
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let mut handler = handler_lock.lock().await;
-        if let Err(e) = handler.deafen(false).await {
-            check_msg(
-                msg.channel_id
-                    .say(&ctx.http, format!("Failed: {:?}", e))
-                    .await,
+#[command]
+#[only_in(guilds)]
+async fn setlocation(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let coordinates = match (args.single::<f64>(), args.single::<f64>()) {
+        (Ok(latitude), Ok(longitude)) if args.is_empty() => Some((latitude, longitude)),
+        _ => None,
+    };
+    let reply = set_location(ctx, msg.guild_id.unwrap(), coordinates, args.message()).await;
+    send_reply(ctx, msg, reply).await;
+    Ok(())
+}
+
+/// Sets the guild's location to `coordinates`, given as latitude and longitude, or else to
+/// the place called `city`.
+async fn set_location(
+    ctx: &Context,
+    guild_id: GuildId,
+    coordinates: Option<(f64, f64)>,
+    city: &str,
+) -> Reply {
+    let provider = ctx
+        .data
+        .read()
+        .await
+        .get::<WeatherSource>()
+        .cloned()
+        .expect("Weather provider was installed at startup.");
+
+    let (name, location) = match coordinates {
+        Some((latitude, longitude)) => {
+            if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+                return Err(String::from(
+                    "Latitude must be within ±90 and longitude within ±180",
+                ));
+            }
+            (
+                format!("{}, {}", latitude, longitude),
+                Location {
+                    latitude,
+                    longitude,
+                },
+            )
+        }
+        None => {
+            let city = city.trim();
+            if city.is_empty() {
+                return Err(String::from(
+                    "Usage: setlocation <latitude> <longitude> or setlocation <city>",
+                ));
+            }
+            match provider.geocode(city).await {
+                Ok(Some(found)) => found,
+                Ok(None) => return Err(format!("Could not find {}", city)),
+                Err(e) => {
+                    println!("Error calling geocoding API: {}", e);
+                    return Err(String::from("Error looking up that city"));
+                }
+            }
+        }
+    };
+
+    let guilds = ctx
+        .data
+        .read()
+        .await
+        .get::<GuildData>()
+        .cloned()
+        .expect("Guild settings were installed at startup.");
+    let saved = guilds
+        .lock()
+        .await
+        .update(guild_id.0, |guild| guild.location = Some(location));
+
+    match saved {
+        Ok(()) => {
+            guild_weather(ctx, guild_id).await.lock().await.expire();
+            let mut reply = format!(
+                "Location set to {} ({}, {})",
+                name, location.latitude, location.longitude
             );
+            // The hours follow the location's zone unless the guild set its own.
+            if guilds.lock().await.get(guild_id.0).timezone.is_none() {
+                reply.push_str(&format!(", time zone {}", zone_at(&location)));
+            }
+            Ok(reply)
+        }
+        Err(e) => {
+            println!("Error saving guild settings: {}", e);
+            Err(String::from("Error saving the location"))
         }
+    }
+}
 
-        check_msg(msg.channel_id.say(&ctx.http, "Undeafened").await);
-    } else {
-        check_msg(
-            msg.channel_id
-                .say(&ctx.http, "Not in a voice channel to undeafen in")
-                .await,
-        );
+#[command]
+#[only_in(guilds)]
+async fn pack(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    send_reply(
+        ctx,
+        msg,
+        choose_pack(ctx, msg.guild_id.unwrap(), args.message()).await,
+    )
+    .await;
+    Ok(())
+}
+
+/// Switches the guild to the pack called `name`.
+async fn choose_pack(ctx: &Context, guild_id: GuildId, name: &str) -> Reply {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(String::from("Usage: pack <name>, see packs for the names"));
     }
 
+    let packs_lock = ctx
+        .data
+        .read()
+        .await
+        .get::<SongMap>()
+        .cloned()
+        .expect("Sound cache was installed at startup.");
+    if !packs_lock.lock().await.packs.contains_key(name) {
+        return Err(format!("No pack named {}", name));
+    }
+
+    let guilds = ctx
+        .data
+        .read()
+        .await
+        .get::<GuildData>()
+        .cloned()
+        .expect("Guild settings were installed at startup.");
+    let saved = guilds
+        .lock()
+        .await
+        .update(guild_id.0, |guild| guild.pack = Some(name.to_string()));
+
+    match saved {
+        Ok(()) => {
+            let session_lock = guild_session(ctx, guild_id).await;
+            let mut session = session_lock.lock().await;
+            if session.pack != name {
+                session.switch_pack();
+            }
+            Ok(format!(
+                "Switched to {}, it starts when the song loops",
+                name
+            ))
+        }
+        Err(e) => {
+            println!("Error saving guild settings: {}", e);
+            Err(String::from("Error saving the pack"))
+        }
+    }
+}
+
+#[command]
+#[only_in(guilds)]
+async fn chime(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let enabled = match args.single::<String>().as_deref() {
+        Ok("on") => Some(true),
+        Ok("off") => Some(false),
+        _ => None,
+    };
+    send_reply(
+        ctx,
+        msg,
+        set_chime(ctx, msg.guild_id.unwrap(), enabled).await,
+    )
+    .await;
     Ok(())
 }
 
+/// Turns the guild's hourly chime on or off, or tells whether it is on.
+async fn set_chime(ctx: &Context, guild_id: GuildId, enabled: Option<bool>) -> Reply {
+    let guilds = ctx
+        .data
+        .read()
+        .await
+        .get::<GuildData>()
+        .cloned()
+        .expect("Guild settings were installed at startup.");
+
+    let enabled = match enabled {
+        Some(enabled) => enabled,
+        None => {
+            let state = if guilds.lock().await.chime(guild_id.0) {
+                "on"
+            } else {
+                "off"
+            };
+            return Err(format!(
+                "The hourly chime is {}. Usage: chime on|off",
+                state
+            ));
+        }
+    };
+
+    let saved = guilds
+        .lock()
+        .await
+        .update(guild_id.0, |guild| guild.chime = Some(enabled));
+    match saved {
+        Ok(()) => Ok(String::from(if enabled {
+            "The hourly chime is on"
+        } else {
+            "The hourly chime is off"
+        })),
+        Err(e) => {
+            println!("Error saving guild settings: {}", e);
+            Err(String::from("Error saving the chime setting"))
+        }
+    }
+}
+
 #[command]
 #[only_in(guilds)]
-async fn weather(ctx: &Context, msg: &Message) -> CommandResult {
-    let weather_cache_lock = ctx
+async fn volume(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let percent = args.single::<u32>().ok();
+    send_reply(
+        ctx,
+        msg,
+        set_volume(ctx, msg.guild_id.unwrap(), percent).await,
+    )
+    .await;
+    Ok(())
+}
+
+/// Sets the guild's volume in percent, or tells what it is.
+async fn set_volume(ctx: &Context, guild_id: GuildId, percent: Option<u32>) -> Reply {
+    let guilds = ctx
         .data
         .read()
         .await
-        .get::<WeatherCache>()
+        .get::<GuildData>()
         .cloned()
-        .expect("Weather cache was installed at startup.");
-    let mut weather_data = weather_cache_lock.lock().await;
-    check_msg(
-        msg.channel_id
-            .say(
-                &ctx.http,
-                format!(
-                    "{:?}",
-                    get_weather(&LOCATION, API_KEY, &mut weather_data)
-                        .await
-                        .unwrap()
-                ),
-            )
-            .await,
-    );
+        .expect("Guild settings were installed at startup.");
+
+    let percent = match percent {
+        Some(percent) if percent <= 200 => percent,
+        _ => {
+            let current = guilds.lock().await.volume(guild_id.0);
+            return Err(format!(
+                "The volume is {}%. Usage: volume <0-200>",
+                (current * 100.0).round()
+            ));
+        }
+    };
+
+    let saved = guilds
+        .lock()
+        .await
+        .update(guild_id.0, |guild| guild.volume = Some(percent));
+    if let Err(e) = saved {
+        println!("Error saving guild settings: {}", e);
+        return Err(String::from("Error saving the volume"));
+    }
+
+    // Change the playing track too, without waiting for the next song.
+    let session_lock = guild_session(ctx, guild_id).await;
+    let mut session = session_lock.lock().await;
+    if let Some(running) = session.fade.take() {
+        running.finish();
+    }
+    if let Some(track) = &session.track {
+        let _ = track.set_volume(session.song_volume * percent as f32 / 100.0);
+    }
+    if let Some(ambient) = &session.ambience {
+        let _ = ambient
+            .track
+            .set_volume(ambient.volume * percent as f32 / 100.0);
+    }
+
+    Ok(format!("Volume set to {}%", percent))
+}
+
+#[command]
+#[only_in(guilds)]
+async fn timezone(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let reply = set_timezone(ctx, msg.guild_id.unwrap(), args.message()).await;
+    send_reply(ctx, msg, reply).await;
     Ok(())
 }
 
+/// Sets the guild's time zone by name, or with "auto" back to the zone at its location.
+/// Without a name it tells which zone the guild uses.
+async fn set_timezone(ctx: &Context, guild_id: GuildId, name: &str) -> Reply {
+    let settings = ctx
+        .data
+        .read()
+        .await
+        .get::<BotSettings>()
+        .cloned()
+        .expect("Settings were installed at startup.");
+    let guilds = ctx
+        .data
+        .read()
+        .await
+        .get::<GuildData>()
+        .cloned()
+        .expect("Guild settings were installed at startup.");
+
+    let name = name.trim();
+    if name.is_empty() {
+        let guilds = guilds.lock().await;
+        let source = if guilds.get(guild_id.0).timezone.is_some() {
+            "set with timezone"
+        } else {
+            "from the location"
+        };
+        return Err(format!(
+            "The time zone is {} ({}). Usage: timezone <name>, e.g. Europe/Paris, or timezone auto",
+            guilds.timezone(guild_id.0, &settings.location),
+            source
+        ));
+    }
+
+    let timezone = if name.eq_ignore_ascii_case("auto") {
+        None
+    } else {
+        match name.parse::<Tz>() {
+            Ok(tz) => Some(tz.name().to_string()),
+            Err(_) => {
+                return Err(format!(
+                    "Unknown time zone {}, use a name like Europe/Paris",
+                    name
+                ))
+            }
+        }
+    };
+
+    let mut guilds = guilds.lock().await;
+    match guilds.update(guild_id.0, |guild| guild.timezone = timezone) {
+        Ok(()) => Ok(format!(
+            "Time zone set to {}, the hour changes by it from the next hour",
+            guilds.timezone(guild_id.0, &settings.location)
+        )),
+        Err(e) => {
+            println!("Error saving guild settings: {}", e);
+            Err(String::from("Error saving the time zone"))
+        }
+    }
+}
+
 #[command]
 #[only_in(guilds)]
-async fn unmute(ctx: &Context, msg: &Message) -> CommandResult {
-    let guild = msg.guild(&ctx.cache).unwrap();
-    let guild_id = guild.id;
+async fn packs(ctx: &Context, msg: &Message) -> CommandResult {
+    send_reply(ctx, msg, list_packs(ctx, msg.guild_id.unwrap()).await).await;
+    Ok(())
+}
 
-    let manager = songbird::get(ctx)
+/// Lists the packs, marking the one the guild plays.
+async fn list_packs(ctx: &Context, guild_id: GuildId) -> Reply {
+    let packs_lock = ctx
+        .data
+        .read()
         .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
+        .get::<SongMap>()
+        .cloned()
+        .expect("Sound cache was installed at startup.");
+    let guilds = ctx
+        .data
+        .read()
+        .await
+        .get::<GuildData>()
+        .cloned()
+        .expect("Guild settings were installed at startup.");
 
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let mut handler = handler_lock.lock().await;
-        if let Err(e) = handler.mute(false).await {
-            check_msg(
-                msg.channel_id
-                    .say(&ctx.http, format!("Failed: {:?}", e))
-                    .await,
-            );
+    let chosen = guilds.lock().await.pack(guild_id.0);
+    let packs = packs_lock.lock().await;
+    let (current, _) = packs.get(chosen.as_deref());
+
+    let mut list = String::from("Song packs:");
+    for (name, pack) in &packs.packs {
+        list.push_str(&format!("\n{} ({} songs)", name, pack.songs.len()));
+        if name == current {
+            list.push_str(" - playing here");
         }
+    }
+    Ok(list)
+}
+
+#[command]
+#[only_in(guilds)]
+async fn unmute(ctx: &Context, msg: &Message) -> CommandResult {
+    send_reply(ctx, msg, set_mute(ctx, msg.guild_id.unwrap(), false).await).await;
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases(np)]
+async fn nowplaying(ctx: &Context, msg: &Message) -> CommandResult {
+    match now_playing(ctx, msg.guild_id.unwrap()).await {
+        Ok(embed) => check_msg(
+            msg.channel_id
+                .send_message(&ctx.http, |message| message.set_embed(embed))
+                .await,
+        ),
+        Err(content) => check_msg(msg.reply(ctx, content).await),
+    }
+    Ok(())
+}
 
-        check_msg(msg.channel_id.say(&ctx.http, "Unmuted").await);
+/// Describes the guild's playing song: its slot and title, the weather it was picked for
+/// and when the next hour's song starts.
+async fn now_playing(ctx: &Context, guild_id: GuildId) -> Result<CreateEmbed, String> {
+    let settings = ctx
+        .data
+        .read()
+        .await
+        .get::<BotSettings>()
+        .cloned()
+        .expect("Settings were installed at startup.");
+    let tz = ctx
+        .data
+        .read()
+        .await
+        .get::<GuildData>()
+        .cloned()
+        .expect("Guild settings were installed at startup.")
+        .lock()
+        .await
+        .timezone(guild_id.0, &settings.location);
+    let packs_lock = ctx
+        .data
+        .read()
+        .await
+        .get::<SongMap>()
+        .cloned()
+        .expect("Sound cache was installed at startup.");
+    let session_lock = guild_session(ctx, guild_id).await;
+    let session = session_lock.lock().await;
+    let weather_cache_lock = guild_weather(ctx, guild_id).await;
+    let weather_data = weather_cache_lock.lock().await;
+
+    let key = match &session.current {
+        Some((key, _)) => *key,
+        None => return Err(String::from("Nothing is playing")),
+    };
+    // Locked after the session, which a guild can hold while it fetches the weather.
+    let title = packs_lock
+        .lock()
+        .await
+        .packs
+        .get(&session.pack)
+        .and_then(|pack| pack.songs.get(&key))
+        .map_or_else(|| key.to_string(), Song::title);
+
+    let mut slot = format!("{}:00, {}", key.hour, key.variant.name());
+    if let Some(season) = key.season {
+        slot.push_str(&format!(", {}", season));
+    }
+    let weather = if weather_data.last_call == DateTime::<Utc>::MIN_UTC {
+        format!("{}, not fetched yet", weather_data.cached_weather.name())
     } else {
+        format!(
+            "{}, fetched <t:{}:R>",
+            weather_data.cached_weather.name(),
+            weather_data.last_call.timestamp()
+        )
+    };
+    let next_hour = start_of_next_hour(&Utc::now().with_timezone(&tz));
+
+    let mut embed = CreateEmbed::default();
+    embed
+        .title(title)
+        .field("Slot", format!("{} ({})", key, slot), true)
+        .field("Pack", &session.pack, true)
+        .field("Weather", weather, false)
+        .field("Loops", session.loops, true)
+        .field(
+            "Next song",
+            format!("<t:{}:R>", next_hour.timestamp()),
+            true,
+        );
+    Ok(embed)
+}
+
+/// Registers the slash commands, which mirror the prefix commands.
+async fn register_slash_commands(http: &Http) -> SerenityResult<Vec<Command>> {
+    Command::set_global_application_commands(http, |commands| {
+        for (name, description) in SIMPLE_COMMANDS {
+            commands.create_application_command(|command| {
+                command
+                    .name(name)
+                    .description(description)
+                    .dm_permission(name == "ping")
+            });
+        }
+        commands
+            .create_application_command(|command| {
+                command
+                    .name("setlocation")
+                    .description("Set the location used for this server's weather")
+                    .dm_permission(false)
+                    .create_option(|option| {
+                        option
+                            .name("latitude")
+                            .description("Latitude, used with longitude")
+                            .kind(CommandOptionType::Number)
+                            .min_number_value(-90.0)
+                            .max_number_value(90.0)
+                    })
+                    .create_option(|option| {
+                        option
+                            .name("longitude")
+                            .description("Longitude, used with latitude")
+                            .kind(CommandOptionType::Number)
+                            .min_number_value(-180.0)
+                            .max_number_value(180.0)
+                    })
+                    .create_option(|option| {
+                        option
+                            .name("city")
+                            .description("City to look up instead of coordinates")
+                            .kind(CommandOptionType::String)
+                    })
+            })
+            .create_application_command(|command| {
+                command
+                    .name("pack")
+                    .description("Switch this server to another song pack")
+                    .dm_permission(false)
+                    .create_option(|option| {
+                        option
+                            .name("name")
+                            .description("Name of the pack, see /packs")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                    })
+            })
+            .create_application_command(|command| {
+                command
+                    .name("chime")
+                    .description("Turn the hourly chime on or off")
+                    .dm_permission(false)
+                    .create_option(|option| {
+                        option
+                            .name("enabled")
+                            .description("Whether the chime plays, leave out to see")
+                            .kind(CommandOptionType::Boolean)
+                    })
+            })
+            .create_application_command(|command| {
+                command
+                    .name("timezone")
+                    .description("Set the time zone this server's hours follow")
+                    .dm_permission(false)
+                    .create_option(|option| {
+                        option
+                            .name("zone")
+                            .description(
+                                "Zone name such as Europe/Paris, or auto for the location's",
+                            )
+                            .kind(CommandOptionType::String)
+                    })
+            })
+            .create_application_command(|command| {
+                command
+                    .name("volume")
+                    .description("Set this server's playback volume")
+                    .dm_permission(false)
+                    .create_option(|option| {
+                        option
+                            .name("percent")
+                            .description("Volume in percent, leave out to see")
+                            .kind(CommandOptionType::Integer)
+                            .min_int_value(0)
+                            .max_int_value(200)
+                    })
+            })
+    })
+    .await
+}
+
+/// The value given for the option called `name`, if any.
+fn option<'a>(
+    command: &'a ApplicationCommandInteraction,
+    name: &str,
+) -> Option<&'a CommandDataOptionValue> {
+    command
+        .data
+        .options
+        .iter()
+        .find(|option| option.name == name)?
+        .resolved
+        .as_ref()
+}
+
+/// Runs a slash command through the same code as its prefix command.
+async fn run_slash_command(ctx: &Context, command: &ApplicationCommandInteraction) {
+    // Starting a song can take longer than Discord waits for the first response.
+    if let Err(e) = command.defer(&ctx.http).await {
+        println!("Error responding to /{}: {:?}", command.data.name, e);
+        return;
+    }
+
+    let name = command.data.name.as_str();
+    if let ("nowplaying", Some(guild_id)) = (name, command.guild_id) {
+        let now_playing = now_playing(ctx, guild_id).await;
         check_msg(
-            msg.channel_id
-                .say(&ctx.http, "Not in a voice channel to unmute in")
+            command
+                .edit_original_interaction_response(&ctx.http, |response| match now_playing {
+                    Ok(embed) => response.set_embed(embed),
+                    Err(content) => response.content(content),
+                })
                 .await,
         );
+        return;
     }
 
-    Ok(())
+    let reply = match (name, command.guild_id) {
+        ("ping", _) => Ok(String::from("Pong!")),
+        (_, None) => Err(String::from("This command only works in servers")),
+        ("play", Some(guild_id)) => {
+            start_playing(ctx, guild_id, command.user.id, command.channel_id).await
+        }
+        ("join", Some(guild_id)) => join_channel(ctx, guild_id, command.user.id).await,
+        ("leave", Some(guild_id)) => leave_channel(ctx, guild_id).await,
+        ("mute", Some(guild_id)) => set_mute(ctx, guild_id, true).await,
+        ("unmute", Some(guild_id)) => set_mute(ctx, guild_id, false).await,
+        ("deafen", Some(guild_id)) => set_deaf(ctx, guild_id, true).await,
+        ("undeafen", Some(guild_id)) => set_deaf(ctx, guild_id, false).await,
+        ("weather", Some(guild_id)) => current_weather(ctx, guild_id).await,
+        ("packs", Some(guild_id)) => list_packs(ctx, guild_id).await,
+        ("setlocation", Some(guild_id)) => {
+            let coordinates = match (option(command, "latitude"), option(command, "longitude")) {
+                (
+                    Some(CommandDataOptionValue::Number(latitude)),
+                    Some(CommandDataOptionValue::Number(longitude)),
+                ) => Some((*latitude, *longitude)),
+                _ => None,
+            };
+            let city = match option(command, "city") {
+                Some(CommandDataOptionValue::String(city)) => city.as_str(),
+                _ => "",
+            };
+            set_location(ctx, guild_id, coordinates, city).await
+        }
+        ("pack", Some(guild_id)) => {
+            let name = match option(command, "name") {
+                Some(CommandDataOptionValue::String(name)) => name.as_str(),
+                _ => "",
+            };
+            choose_pack(ctx, guild_id, name).await
+        }
+        ("chime", Some(guild_id)) => {
+            let enabled = match option(command, "enabled") {
+                Some(CommandDataOptionValue::Boolean(enabled)) => Some(*enabled),
+                _ => None,
+            };
+            set_chime(ctx, guild_id, enabled).await
+        }
+        ("timezone", Some(guild_id)) => {
+            let zone = match option(command, "zone") {
+                Some(CommandDataOptionValue::String(zone)) => zone.as_str(),
+                _ => "",
+            };
+            set_timezone(ctx, guild_id, zone).await
+        }
+        ("volume", Some(guild_id)) => {
+            let percent = match option(command, "percent") {
+                Some(CommandDataOptionValue::Integer(percent)) => u32::try_from(*percent).ok(),
+                _ => None,
+            };
+            set_volume(ctx, guild_id, percent).await
+        }
+        _ => Err(format!("Unknown command {}", name)),
+    };
+
+    let content = match reply {
+        Ok(content) | Err(content) => content,
+    };
+    check_msg(
+        command
+            .edit_original_interaction_response(&ctx.http, |response| response.content(content))
+            .await,
+    );
+}
+
+/// The voice channel `user_id` is in, if any.
+fn user_voice_channel(ctx: &Context, guild_id: GuildId, user_id: UserId) -> Option<ChannelId> {
+    guild_id
+        .to_guild_cached(&ctx.cache)?
+        .voice_states
+        .get(&user_id)?
+        .channel_id
+}
+
+/// Sends the outcome of a prefix command: what it did to the channel, or why it did
+/// nothing as a reply to whoever ran it.
+async fn send_reply(ctx: &Context, msg: &Message, reply: Reply) {
+    match reply {
+        Ok(content) => check_msg(msg.channel_id.say(&ctx.http, content).await),
+        Err(content) => check_msg(msg.reply(ctx, content).await),
+    }
 }
 
 /// Checks that a message successfully sent; if not, then logs why to stdout.
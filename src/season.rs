@@ -0,0 +1,94 @@
+extern crate chrono;
+extern crate serde;
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::Datelike;
+use serde::Deserialize;
+
+use crate::weather::Location;
+
+/// Every season, in the order they come in the northern hemisphere's year.
+pub const SEASONS: [Season; 4] = [
+    Season::Spring,
+    Season::Summer,
+    Season::Autumn,
+    Season::Winter,
+];
+
+/// Meteorological season: spring is March to May in the northern hemisphere and
+/// September to November in the southern one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Season {
+    /// The season on `date` at `location`, flipped south of the equator.
+    pub fn at<D: Datelike>(date: &D, location: &Location) -> Self {
+        let northern = match date.month() {
+            3..=5 => Season::Spring,
+            6..=8 => Season::Summer,
+            9..=11 => Season::Autumn,
+            _ => Season::Winter,
+        };
+        if location.latitude < 0.0 {
+            northern.opposite()
+        } else {
+            northern
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Season::Spring => Season::Autumn,
+            Season::Summer => Season::Winter,
+            Season::Autumn => Season::Spring,
+            Season::Winter => Season::Summer,
+        }
+    }
+
+    /// The name used in song file names and the manifest.
+    pub fn name(self) -> &'static str {
+        match self {
+            Season::Spring => "spring",
+            Season::Summer => "summer",
+            Season::Autumn => "autumn",
+            Season::Winter => "winter",
+        }
+    }
+}
+
+impl fmt::Display for Season {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Season {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "fall" => Ok(Season::Autumn),
+            name => SEASONS
+                .iter()
+                .copied()
+                .find(|season| season.name() == name)
+                .ok_or_else(|| format!("unknown season \"{}\"", name)),
+        }
+    }
+}
+
+impl TryFrom<String> for Season {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        name.parse()
+    }
+}
@@ -0,0 +1,177 @@
+extern crate songbird;
+
+use std::collections::HashSet;
+
+use songbird::input::cached::Compressed;
+use songbird::tracks::TrackHandle;
+
+use crate::ambience::AmbientTrack;
+use crate::library::SongKey;
+use crate::transition::Fade;
+
+/// Playback state of one guild. The hourly and weather event handlers of the guild's call
+/// share it, so guilds playing at the same time never touch each other's tracks.
+/// `Audio` is the cached audio of a song, only something else in tests.
+pub struct Session<Audio = Compressed> {
+    /// Pack the current and prefetched songs are from.
+    pub pack: String,
+    /// Key and audio of the song playing now.
+    pub current: Option<(SongKey, Audio)>,
+    /// Key and audio of the song prefetched for the next hour.
+    pub next: Option<(SongKey, Audio)>,
+    /// Handle of the playing track.
+    pub track: Option<TrackHandle>,
+    /// Volume the playing song's gain and loudness normalization give it, before the
+    /// guild's volume. Kept while the song plays on after a reload or a pack switch.
+    pub song_volume: f32,
+    /// Times the playing song has looped.
+    pub loops: u32,
+    /// Ambient loop playing under the song.
+    pub ambience: Option<AmbientTrack>,
+    /// Crossfade from the previous track that is still running.
+    pub fade: Option<Fade>,
+    /// Set when the playing song's file changed, or the song due could not be read, so the
+    /// next loop starts the right song.
+    pub reload: bool,
+}
+
+// Derived, it would only exist for audio that has a default.
+impl<Audio> Default for Session<Audio> {
+    fn default() -> Self {
+        Session {
+            pack: String::new(),
+            current: None,
+            next: None,
+            track: None,
+            song_volume: 1.0,
+            loops: 0,
+            ambience: None,
+            fade: None,
+            reload: false,
+        }
+    }
+}
+
+impl<Audio: Clone> Session<Audio> {
+    /// Returns the audio for `key` if it is playing or was prefetched. A prefetched song
+    /// for any other key is kept, so a weather change mid-hour does not throw away the
+    /// next hour's song.
+    pub fn cached(&mut self, key: SongKey) -> Option<Audio> {
+        if let Some((current_key, current)) = &self.current {
+            if *current_key == key {
+                return Some(current.clone());
+            }
+        }
+
+        match &self.next {
+            Some((next_key, _)) if *next_key == key => self.next.take().map(|(_, next)| next),
+            _ => None,
+        }
+    }
+
+    /// Records the song that just started playing at `song_volume`, see `Song::volume`.
+    pub fn set_current(
+        &mut self,
+        pack: &str,
+        key: SongKey,
+        song: Audio,
+        track: TrackHandle,
+        song_volume: f32,
+    ) {
+        self.pack = pack.to_string();
+        self.current = Some((key, song));
+        self.track = Some(track);
+        self.song_volume = song_volume;
+        self.loops = 0;
+        self.reload = false;
+    }
+
+    /// Drops the cached audio of songs whose files changed. The playing track keeps going
+    /// until its next loop, which picks up the new file.
+    pub fn invalidate(&mut self, pack: &str, changed: &HashSet<SongKey>) {
+        if self.pack != pack {
+            return;
+        }
+        if matches!(&self.current, Some((key, _)) if changed.contains(key)) {
+            self.current = None;
+            self.reload = true;
+        }
+        if matches!(&self.next, Some((key, _)) if changed.contains(key)) {
+            self.next = None;
+        }
+    }
+
+    /// Whether the next hour still needs a song prefetched for `key`.
+    pub fn needs_prefetch(&self, key: SongKey) -> bool {
+        match &self.next {
+            Some((next_key, _)) => *next_key != key,
+            None => true,
+        }
+    }
+
+    /// Drops the cached songs after the guild picked another pack, so the next loop
+    /// starts the new pack's song.
+    pub fn switch_pack(&mut self) {
+        self.current = None;
+        self.next = None;
+        self.reload = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weather::Weather;
+
+    fn this_hour() -> SongKey {
+        SongKey::new(Weather::Clear, 9)
+    }
+
+    fn next_hour() -> SongKey {
+        SongKey::new(Weather::Clear, 10)
+    }
+
+    /// A session of the pack "a" playing this hour's song, with the next hour's prefetched.
+    fn session() -> Session<&'static str> {
+        Session {
+            pack: String::from("a"),
+            current: Some((this_hour(), "this hour")),
+            next: Some((next_hour(), "next hour")),
+            ..Session::default()
+        }
+    }
+
+    #[test]
+    fn other_keys_keep_the_prefetched_song() {
+        let mut session = session();
+        assert_eq!(session.cached(SongKey::new(Weather::Rainy, 9)), None);
+        assert_eq!(session.cached(this_hour()), Some("this hour"));
+        assert_eq!(session.next, Some((next_hour(), "next hour")));
+        assert!(!session.needs_prefetch(next_hour()));
+    }
+
+    #[test]
+    fn the_prefetched_key_takes_the_prefetched_song() {
+        let mut session = session();
+        assert_eq!(session.cached(next_hour()), Some("next hour"));
+        assert_eq!(session.next, None);
+        assert!(session.needs_prefetch(next_hour()));
+    }
+
+    #[test]
+    fn invalidate_only_drops_changed_songs_of_the_playing_pack() {
+        let mut session = session();
+        let changed = HashSet::from([this_hour(), next_hour()]);
+        session.invalidate("b", &changed);
+        assert!(session.current.is_some() && session.next.is_some());
+        assert!(!session.reload);
+
+        session.invalidate("a", &HashSet::from([next_hour()]));
+        assert!(session.current.is_some() && session.next.is_none());
+        assert!(!session.reload);
+
+        session.invalidate("a", &changed);
+        assert!(session.current.is_none());
+        assert!(session.reload);
+    }
+}
@@ -0,0 +1,219 @@
+extern crate serde;
+extern crate toml;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use std::{env, fs};
+
+use serde::Deserialize;
+
+use crate::weather::{Location, Weather};
+
+/// Settings file read when `NOOKU_SETTINGS` is not set.
+pub const DEFAULT_SETTINGS_PATH: &str = "settings.toml";
+
+/// Environment variable checked first for the weather API key.
+pub const API_KEY_VAR: &str = "OPENWEATHER_API_KEY";
+
+/// Secrets file checked last for the weather API key.
+pub const API_KEY_SECRETS_PATH: &str = "api_key";
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub prefix: String,
+    /// Whether to read prefix commands from messages, which needs the privileged message
+    /// content intent. Slash commands work either way.
+    pub prefix_commands: bool,
+    /// File the per-guild settings are saved in.
+    pub guild_data: String,
+    /// Location used by guilds that have not set their own.
+    pub location: Location,
+    pub songs: SongSettings,
+    pub ambience: AmbienceSettings,
+    pub weather: WeatherSettings,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SongSettings {
+    /// Songs folder used when `packs` is not set.
+    pub path: String,
+    pub bitrate: i32,
+    /// Seconds the old song fades out while the new one fades in, 0 to cut straight over.
+    pub crossfade: f64,
+    /// Loudness in LUFS every song is brought to, `None` to play songs as they are.
+    /// Off unless the settings file sets it, so removing the key turns normalization off.
+    #[serde(default)]
+    pub loudness_target: Option<f64>,
+    /// File the measured loudness of each song is saved in.
+    pub loudness_cache: String,
+    /// Chime played at the top of the hour by packs that do not set their own.
+    pub chime: Option<String>,
+    /// Folder whose subfolders are each a pack of songs.
+    pub packs: Option<String>,
+    /// Pack played by guilds that have not picked one, the first pack by name if not set.
+    pub default_pack: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AmbienceSettings {
+    /// Folder of ambient loops named after the weather they play in, `None` for no ambience.
+    pub path: Option<String>,
+    /// Volume of the ambience in the heaviest weather, relative to the music.
+    pub volume: f32,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum ProviderKind {
+    #[serde(rename = "openweathermap")]
+    OpenWeatherMap,
+    #[serde(rename = "open-meteo")]
+    OpenMeteo,
+    #[serde(rename = "fixed")]
+    Fixed,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct WeatherSettings {
+    /// Which weather backend to use.
+    pub provider: ProviderKind,
+    /// Weather always reported by the fixed provider.
+    pub fixed_weather: Weather,
+    /// Minutes to wait between calls to the weather API.
+    pub cooldown: i64,
+    /// Variants to try, in order, when the library has no song for a weather.
+    /// Weathers not listed use `Weather::default_fallbacks`.
+    pub fallbacks: HashMap<Weather, Vec<Weather>>,
+    /// File containing the weather API key, checked after `OPENWEATHER_API_KEY`.
+    pub api_key_file: Option<String>,
+    /// The key found at startup, `None` if no source had one.
+    #[serde(skip)]
+    pub api_key: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            prefix: String::from("~"),
+            prefix_commands: true,
+            guild_data: String::from("guilds.json"),
+            location: Location {
+                latitude: 34.221924,
+                longitude: -79.814693,
+            },
+            songs: SongSettings::default(),
+            ambience: AmbienceSettings::default(),
+            weather: WeatherSettings::default(),
+        }
+    }
+}
+
+impl Default for SongSettings {
+    fn default() -> Self {
+        SongSettings {
+            path: String::from("songs/"),
+            bitrate: 128_000,
+            crossfade: 3.0,
+            loudness_target: None,
+            loudness_cache: String::from("loudness.json"),
+            chime: None,
+            packs: None,
+            default_pack: None,
+        }
+    }
+}
+
+impl Default for AmbienceSettings {
+    fn default() -> Self {
+        AmbienceSettings {
+            path: None,
+            volume: 0.5,
+        }
+    }
+}
+
+impl Default for WeatherSettings {
+    fn default() -> Self {
+        WeatherSettings {
+            provider: ProviderKind::OpenWeatherMap,
+            fixed_weather: Weather::Clear,
+            cooldown: 10,
+            fallbacks: HashMap::new(),
+            api_key_file: None,
+            api_key: None,
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings file at `path`. A missing file gives the default settings,
+    /// any other failure is returned as a message.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut settings: Settings = match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| format!("Invalid {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                println!(
+                    "No settings file found at {}, using default settings.",
+                    path.display()
+                );
+                Settings::default()
+            }
+            Err(e) => return Err(format!("Could not read {}: {}", path.display(), e)),
+        };
+
+        settings.weather.api_key = settings.weather.find_api_key();
+
+        Ok(settings)
+    }
+}
+
+impl SongSettings {
+    /// The folder holding every song: the packs folder, or the songs folder.
+    pub fn root(&self) -> &str {
+        self.packs.as_deref().unwrap_or(&self.path)
+    }
+
+    pub fn crossfade(&self) -> Duration {
+        Duration::from_secs_f64(self.crossfade.max(0.0))
+    }
+}
+
+impl WeatherSettings {
+    /// The variants to try after `weather` when the library has no song for it.
+    pub fn fallback_chain(&self, weather: Weather) -> Vec<Weather> {
+        self.fallbacks
+            .get(&weather)
+            .cloned()
+            .unwrap_or_else(|| weather.default_fallbacks())
+    }
+
+    /// Looks for the API key in the environment, then the configured file, then the secrets file.
+    fn find_api_key(&self) -> Option<String> {
+        if let Ok(key) = env::var(API_KEY_VAR) {
+            if !key.trim().is_empty() {
+                return Some(key.trim().to_string());
+            }
+        }
+
+        let files = self
+            .api_key_file
+            .iter()
+            .map(String::as_str)
+            .chain([API_KEY_SECRETS_PATH]);
+        for file in files {
+            match fs::read_to_string(file) {
+                Ok(key) if !key.trim().is_empty() => return Some(key.trim().to_string()),
+                Ok(_) => println!("Weather API key file {} is empty.", file),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => println!("Could not read weather API key file {}: {}", file, e),
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,87 @@
+extern crate chrono;
+extern crate chrono_tz;
+extern crate tzf_rs;
+
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Duration, TimeZone, Timelike};
+use chrono_tz::Tz;
+use tzf_rs::DefaultFinder;
+
+use crate::weather::Location;
+
+/// Zone boundary polygons bundled with tzf-rs, loaded on the first lookup.
+fn finder() -> &'static DefaultFinder {
+    static FINDER: OnceLock<DefaultFinder> = OnceLock::new();
+    FINDER.get_or_init(DefaultFinder::new)
+}
+
+/// The time zone at `location`, looked up offline in the zone boundaries. Places outside
+/// every zone, such as open sea, get the nautical zone of their longitude.
+pub fn zone_at(location: &Location) -> Tz {
+    let name = finder().get_tz_name(location.longitude, location.latitude);
+    name.parse()
+        .unwrap_or_else(|_| nautical_zone(location.longitude))
+}
+
+/// The whole-hour zone 15 degrees of longitude wide around `longitude`.
+fn nautical_zone(longitude: f64) -> Tz {
+    let offset = (longitude / 15.0).round() as i32;
+    // The Etc zones have their sign reversed: Etc/GMT+5 is five hours behind UTC.
+    let name = match offset {
+        0 => String::from("Etc/GMT"),
+        _ => format!("Etc/GMT{:+}", -offset),
+    };
+    name.parse().unwrap_or(Tz::UTC)
+}
+
+/// The start of the hour after `now`, in the zone of `now`. Zones half an hour off UTC
+/// change hours at half past.
+pub fn start_of_next_hour<T: TimeZone>(now: &DateTime<T>) -> DateTime<T> {
+    let into_hour = Duration::minutes(now.minute().into())
+        + Duration::seconds(now.second().into())
+        + Duration::nanoseconds(now.nanosecond().into());
+    now.clone() + Duration::hours(1) - into_hour
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn zone(latitude: f64, longitude: f64) -> Tz {
+        zone_at(&Location {
+            latitude,
+            longitude,
+        })
+    }
+
+    #[test]
+    fn cities_get_their_zone() {
+        assert_eq!(zone(36.16, -86.78), Tz::America__Chicago); // Nashville
+        assert_eq!(zone(33.52, -86.80), Tz::America__Chicago); // Birmingham, AL
+        assert_eq!(zone(30.42, -87.22), Tz::America__Chicago); // Pensacola
+        assert_eq!(zone(35.22, -101.83), Tz::America__Chicago); // Amarillo
+        assert_eq!(zone(38.88, -6.97), Tz::Europe__Madrid); // Badajoz
+        assert_eq!(zone(19.07, 72.87), Tz::Asia__Kolkata); // Mumbai
+        assert_eq!(zone(40.71, -74.01), Tz::America__New_York);
+        assert_eq!(zone(-33.86, 151.21), Tz::Australia__Sydney);
+    }
+
+    #[test]
+    fn open_sea_gets_a_nautical_zone() {
+        assert_eq!(zone(-40.0, -130.0), Tz::Etc__GMTPlus9);
+        assert_eq!(zone(0.0, -2.0), Tz::Etc__GMT);
+    }
+
+    #[test]
+    fn next_hour_follows_half_hour_zones() {
+        let now = Utc
+            .with_ymd_and_hms(2024, 1, 1, 10, 12, 5)
+            .unwrap()
+            .with_timezone(&Tz::Asia__Kolkata);
+        let next = start_of_next_hour(&now);
+        assert_eq!((next.hour(), next.minute(), next.second()), (16, 0, 0));
+    }
+}
@@ -0,0 +1,127 @@
+extern crate songbird;
+extern crate tokio;
+
+use std::f32::consts::FRAC_PI_2;
+use std::path::Path;
+use std::time::Duration;
+
+use songbird::input::{self, Input};
+use songbird::tracks::{create_player, TrackHandle};
+use songbird::Call;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+use crate::session::Session;
+
+/// Time between volume changes of a crossfade.
+const FADE_STEP: Duration = Duration::from_millis(50);
+
+/// How long the old hour's song takes to fade out under the chime.
+const CHIME_FADE: Duration = Duration::from_millis(1500);
+
+/// A crossfade in progress: the task changing the volumes and the track fading out.
+pub struct Fade {
+    task: JoinHandle<()>,
+    old: TrackHandle,
+}
+
+impl Fade {
+    /// Ends the fade early, stopping the track that was fading out.
+    pub fn finish(self) {
+        self.task.abort();
+        let _ = self.old.stop();
+    }
+}
+
+/// Starts `source` at `volume` as the guild's new song. The session's playing track fades
+/// out over `fade` while the new one fades in, then stops. Without a fade, or with nothing
+/// playing, the new track replaces the session's track straight away. The ambience keeps
+/// playing either way.
+pub fn start_track(
+    call: &mut Call,
+    source: Input,
+    volume: f32,
+    session: &mut Session,
+    fade: Duration,
+) -> TrackHandle {
+    // A fade still running when the next song starts would fight over the volumes.
+    if let Some(running) = session.fade.take() {
+        running.finish();
+    }
+
+    let (mut track, handle) = create_player(source);
+    match session.track.clone() {
+        Some(old) if !fade.is_zero() => {
+            track.set_volume(0.0);
+            call.play(track);
+            session.fade = Some(Fade {
+                task: tokio::spawn(crossfade(old.clone(), Some(handle.clone()), volume, fade)),
+                old,
+            });
+        }
+        old => {
+            if let Some(old) = old {
+                let _ = old.stop();
+            }
+            track.set_volume(volume);
+            call.play(track);
+        }
+    }
+    handle
+}
+
+/// Plays `chime` at `volume` over the session's song while the song fades out, and returns
+/// once the chime has finished.
+pub async fn play_chime(call_lock: &Mutex<Call>, session: &mut Session, chime: &Path, volume: f32) {
+    let source = match input::ffmpeg(chime).await {
+        Ok(source) => source,
+        Err(e) => {
+            println!("Could not play the chime {}: {:?}", chime.display(), e);
+            return;
+        }
+    };
+
+    if let Some(running) = session.fade.take() {
+        running.finish();
+    }
+    let old = session.track.clone();
+    let (mut track, chime_track) = create_player(source);
+    track.set_volume(volume);
+    call_lock.lock().await.play(track);
+    if let Some(old) = old {
+        crossfade(old, None, 0.0, CHIME_FADE).await;
+    }
+
+    let mut interval = tokio::time::interval(Duration::from_millis(100));
+    loop {
+        interval.tick().await;
+        match chime_track.get_info().await {
+            Ok(state) if !state.playing.is_done() => {}
+            _ => break,
+        }
+    }
+}
+
+/// Moves the volume from `old` to `new` with an equal power curve, so the loudness stays
+/// level halfway through, then stops `old`. Without `new` it only fades `old` out.
+async fn crossfade(old: TrackHandle, new: Option<TrackHandle>, volume: f32, fade: Duration) {
+    let old_volume = match old.get_info().await {
+        Ok(state) => state.volume,
+        // The old track already ended.
+        Err(_) => 0.0,
+    };
+
+    let steps = (fade.as_millis() / FADE_STEP.as_millis()).max(1);
+    let mut interval = tokio::time::interval(FADE_STEP);
+    for step in 1..=steps {
+        interval.tick().await;
+        let angle = step as f32 / steps as f32 * FRAC_PI_2;
+        let _ = old.set_volume(old_volume * angle.cos());
+        if let Some(new) = &new {
+            if new.set_volume(volume * angle.sin()).is_err() {
+                break;
+            }
+        }
+    }
+    let _ = old.stop();
+}
@@ -1,105 +1,468 @@
+extern crate async_trait;
 extern crate chrono;
 extern crate reqwest;
+extern crate serde;
 extern crate serde_json;
 
+mod error;
+mod fixed;
+mod open_meteo;
+mod openweathermap;
+
+pub use error::WeatherError;
+pub use fixed::FixedWeather;
+pub use open_meteo::OpenMeteo;
+pub use openweathermap::OpenWeatherMap;
+
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use async_trait::async_trait;
 use chrono::*;
-use reqwest::*;
+use reqwest::{Client, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::{ProviderKind, WeatherSettings, API_KEY_SECRETS_PATH, API_KEY_VAR};
+
+pub type WeatherResult<T> = std::result::Result<T, WeatherError>;
+
+/// Every variant, in key digit order.
+pub const WEATHERS: [Weather; 9] = [
+    Weather::Clear,
+    Weather::Rainy,
+    Weather::Snowy,
+    Weather::Thunderstorm,
+    Weather::Foggy,
+    Weather::Cloudy,
+    Weather::Windy,
+    Weather::HeavySnow,
+    Weather::Unknown,
+];
 
-const API_URL: &str = "https://api.openweathermap.org/data/2.5/";
+/// How long a weather API call may take before it fails with `WeatherError::Network`.
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
 
-const API_COOLDOWN: i64 = 10;
+/// Wind speed in km/h from which clear or cloudy weather counts as windy.
+pub const WINDY_SPEED: f64 = 40.0;
 
-const WEATHER_ON_ERROR: &str = "{
-    \"weather\":[{\"description\":\"clear sky\",\"icon\":\"01d\",\"id\":800,\"main\":\"Clear\"}],
-}";
+/// Intensities of light, moderate and heavy weather, see `Report`.
+pub const LIGHT: f32 = 0.35;
+pub const MODERATE: f32 = 0.65;
+pub const HEAVY: f32 = 1.0;
 
-#[derive(Debug, PartialEq)]
+/// How heavy wind of `wind_speed` km/h is, heaviest at twice `WINDY_SPEED`.
+fn wind_intensity(wind_speed: f64) -> f32 {
+    (wind_speed / (2.0 * WINDY_SPEED)).clamp(LIGHT as f64, HEAVY as f64) as f32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
 pub enum Weather {
     Clear,
     Rainy,
     Snowy,
+    Thunderstorm,
+    Foggy,
+    Cloudy,
+    Windy,
+    HeavySnow,
     Unknown,
 }
 
 impl Weather {
-    pub fn from_id(id: &str) -> Self {
-        match id.chars().nth(0).unwrap_or_default() {
-            '2' | '3' | '5' => Weather::Rainy,
-            '6' => Weather::Snowy,
-            '7' => Weather::Unknown, // TODO represents atmospheric conditions
-            '8' => Weather::Clear,
+    /// Converts an OpenWeatherMap weather condition id along with the wind speed in km/h.
+    pub fn from_id(id: u32, wind_speed: f64) -> Self {
+        let weather = match id {
+            200..=299 => Weather::Thunderstorm,
+            300..=399 | 500..=599 => Weather::Rainy,
+            602 | 622 => Weather::HeavySnow,
+            600..=699 => Weather::Snowy,
+            771 | 781 => Weather::Windy,
+            700..=799 => Weather::Foggy,
+            800 | 801 => Weather::Clear,
+            802..=804 => Weather::Cloudy,
             _ => Weather::Unknown,
+        };
+        weather.in_wind(wind_speed)
+    }
+
+    /// How heavy the weather of an OpenWeatherMap condition id is. Wind is as heavy as its
+    /// speed in km/h is past `WINDY_SPEED`.
+    pub fn intensity_from_id(id: u32, wind_speed: f64) -> f32 {
+        match id {
+            200 | 210 | 230 | 300 | 310 | 500 | 520 | 600 | 612 | 615 | 620 => LIGHT,
+            202 | 212 | 232 | 302 | 312 | 314 | 502..=504 | 522 | 602 | 622 | 781 => HEAVY,
+            800..=804 if wind_speed >= WINDY_SPEED => wind_intensity(wind_speed),
+            _ => MODERATE,
         }
     }
+
+    /// Converts a WMO weather interpretation code, as used by Open-Meteo, along with
+    /// the wind speed in km/h.
+    pub fn from_wmo_code(code: u64, wind_speed: f64) -> Self {
+        let weather = match code {
+            0 | 1 => Weather::Clear,
+            2 | 3 => Weather::Cloudy,
+            45 | 48 => Weather::Foggy,
+            51..=67 | 80..=82 => Weather::Rainy,
+            75 | 86 => Weather::HeavySnow,
+            71..=77 | 85 => Weather::Snowy,
+            95..=99 => Weather::Thunderstorm,
+            _ => Weather::Unknown,
+        };
+        weather.in_wind(wind_speed)
+    }
+
+    /// How heavy the weather of a WMO code is. Wind is as heavy as its speed in km/h is
+    /// past `WINDY_SPEED`.
+    pub fn intensity_from_wmo_code(code: u64, wind_speed: f64) -> f32 {
+        match code {
+            51 | 56 | 61 | 66 | 71 | 77 | 80 | 85 => LIGHT,
+            55 | 57 | 65 | 67 | 75 | 82 | 86 | 99 => HEAVY,
+            0..=3 if wind_speed >= WINDY_SPEED => wind_intensity(wind_speed),
+            _ => MODERATE,
+        }
+    }
+
+    /// Clear or cloudy weather counts as windy once the wind in km/h reaches `WINDY_SPEED`.
+    fn in_wind(self, wind_speed: f64) -> Self {
+        match self {
+            Weather::Clear | Weather::Cloudy if wind_speed >= WINDY_SPEED => Weather::Windy,
+            _ => self,
+        }
+    }
+
+    /// The digit that starts the file names of this variant, see songs/README.txt.
+    pub fn key_digit(self) -> char {
+        match self {
+            Weather::Clear => '0',
+            Weather::Rainy => '1',
+            Weather::Snowy => '2',
+            Weather::Thunderstorm => '3',
+            Weather::Foggy => '4',
+            Weather::Cloudy => '5',
+            Weather::Windy => '6',
+            Weather::HeavySnow => '7',
+            Weather::Unknown => '0',
+        }
+    }
+
+    /// The variant a file name's first digit stands for. '0' is clear.
+    pub fn from_key_digit(digit: char) -> Option<Self> {
+        WEATHERS
+            .iter()
+            .copied()
+            .find(|weather| *weather != Weather::Unknown && weather.key_digit() == digit)
+    }
+
+    /// The name used for this variant in the settings file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Weather::Clear => "clear",
+            Weather::Rainy => "rainy",
+            Weather::Snowy => "snowy",
+            Weather::Thunderstorm => "thunderstorm",
+            Weather::Foggy => "foggy",
+            Weather::Cloudy => "cloudy",
+            Weather::Windy => "windy",
+            Weather::HeavySnow => "heavy-snow",
+            Weather::Unknown => "unknown",
+        }
+    }
+
+    /// Variants tried in order when the library has no song for this one.
+    pub fn default_fallbacks(self) -> Vec<Weather> {
+        match self {
+            Weather::Clear => vec![],
+            Weather::Rainy | Weather::Snowy | Weather::Cloudy => vec![Weather::Clear],
+            Weather::Thunderstorm => vec![Weather::Rainy, Weather::Clear],
+            Weather::Foggy | Weather::Windy => vec![Weather::Cloudy, Weather::Clear],
+            Weather::HeavySnow => vec![Weather::Snowy, Weather::Clear],
+            Weather::Unknown => vec![Weather::Clear],
+        }
+    }
+}
+
+impl FromStr for Weather {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        WEATHERS
+            .iter()
+            .copied()
+            .find(|weather| weather.name() == name)
+            .ok_or_else(|| format!("unknown weather \"{}\"", name))
+    }
+}
+
+// Lets the settings file use weathers as table keys, which toml cannot do for plain enums.
+impl TryFrom<String> for Weather {
+    type Error = String;
+
+    fn try_from(name: String) -> std::result::Result<Self, Self::Error> {
+        name.parse()
+    }
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Location {
     pub longitude: f64,
     pub latitude: f64,
 }
 
+/// The weather at a place and how heavy it is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Report {
+    pub weather: Weather,
+    /// From 0 to 1, e.g. `LIGHT` for light rain and `HEAVY` for heavy rain.
+    pub intensity: f32,
+}
+
+/// A source of weather reports. The backend is picked with the `provider` weather setting.
+#[async_trait]
+pub trait WeatherProvider: Send + Sync {
+    /// The weather right now at `loc`.
+    async fn current(&self, loc: &Location) -> WeatherResult<Report>;
+
+    /// The weather forecast at `loc` for the time closest to `at`.
+    async fn forecast(&self, loc: &Location, at: DateTime<Utc>) -> WeatherResult<Weather>;
+
+    /// Looks up a city name and returns the name that matched along with its location.
+    /// Returns `None` if the city was not found.
+    async fn geocode(&self, city: &str) -> WeatherResult<Option<(String, Location)>>;
+}
+
+/// Creates the provider chosen in the settings. OpenWeatherMap without an API key
+/// falls back to always clear weather.
+pub fn provider_from_settings(settings: &WeatherSettings) -> Box<dyn WeatherProvider> {
+    match settings.provider {
+        ProviderKind::OpenWeatherMap => match &settings.api_key {
+            Some(key) => Box::new(OpenWeatherMap::new(key.clone())),
+            None => {
+                println!(
+                    "Warning: no weather API key found in {}, the api_key_file setting or {}. Defaulting to clear weather.",
+                    API_KEY_VAR, API_KEY_SECRETS_PATH
+                );
+                Box::new(FixedWeather(Weather::Clear))
+            }
+        },
+        ProviderKind::OpenMeteo => Box::new(OpenMeteo),
+        ProviderKind::Fixed => Box::new(FixedWeather(settings.fixed_weather)),
+    }
+}
+
 pub struct WeatherData {
     pub last_call: DateTime<Utc>,
     pub cached_weather: Weather,
+    /// Intensity of `cached_weather`.
+    pub cached_intensity: f32,
     pub playing_weather: Weather,
+    pub last_forecast_call: DateTime<Utc>,
+    /// The time the last forecast was for, and the weather it predicted.
+    pub cached_forecast: Option<(DateTime<Utc>, Weather)>,
+}
+
+impl WeatherData {
+    /// Weather data that has never been fetched, so the next `get_weather` calls the API.
+    pub fn new() -> Self {
+        WeatherData {
+            last_call: DateTime::<Utc>::MIN_UTC,
+            cached_weather: Weather::Clear,
+            cached_intensity: MODERATE,
+            playing_weather: Weather::Clear,
+            last_forecast_call: DateTime::<Utc>::MIN_UTC,
+            cached_forecast: None,
+        }
+    }
+
+    /// Forces the next `get_weather` to call the API, e.g. after the location changed.
+    pub fn expire(&mut self) {
+        self.last_call = DateTime::<Utc>::MIN_UTC;
+        self.last_forecast_call = DateTime::<Utc>::MIN_UTC;
+        self.cached_forecast = None;
+    }
+}
+
+impl Default for WeatherData {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The client every weather API call goes through, so a stalled connection times out
+/// instead of holding up the guild's handler.
+fn client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("The HTTP client has a valid configuration.")
+    })
+}
+
+/// Fetches `url` and parses the JSON body, turning failed statuses into errors.
+async fn get_json<T: DeserializeOwned>(url: Url) -> WeatherResult<T> {
+    let resp = client().get(url).send().await?;
+    match resp.status() {
+        StatusCode::UNAUTHORIZED => return Err(WeatherError::Auth),
+        status if !status.is_success() => return Err(WeatherError::Status(status)),
+        _ => {}
+    }
+
+    let body = resp.text().await?;
+    Ok(serde_json::from_str(&body)?)
 }
 
 pub async fn get_weather(
+    provider: &dyn WeatherProvider,
     loc: &Location,
-    api_key: &str,
+    settings: &WeatherSettings,
     weather_data: &mut WeatherData,
-) -> Result<Weather> {
+) -> WeatherResult<Weather> {
     let time_since_last_call = Utc::now().signed_duration_since(weather_data.last_call);
     println!(
         "Time since last call to weather API: {} min.",
         time_since_last_call.num_minutes()
     );
-    if time_since_last_call > Duration::minutes(API_COOLDOWN) {
+    if time_since_last_call > Duration::minutes(settings.cooldown) {
         weather_data.last_call = Utc::now();
 
         println!("Calling weather API");
-        let resp = call_weather_api(loc, api_key).await.unwrap();
-
-        let json: serde_json::Value = match serde_json::from_str(&resp) {
-            Ok(val) => val,
-            Err(_) => {
-                println!("Error when calling weather API... defaulting to clear weather.");
-                serde_json::from_str(WEATHER_ON_ERROR).unwrap()
-            }
-        };
+        let report = provider.current(loc).await?;
 
-        let weather_id = json
-            .get("weather")
-            .unwrap()
-            .get(0)
-            .unwrap()
-            .get("id")
-            .unwrap()
-            .to_string();
+        weather_data.cached_weather = report.weather;
+        weather_data.cached_intensity = report.intensity;
 
-        println!("Weather_ID: {}", weather_id);
-        weather_data.cached_weather = Weather::from_id(&weather_id);
-
-        Ok(Weather::from_id(&weather_id))
+        Ok(report.weather)
     } else {
-        match weather_data.cached_weather {
-            Weather::Clear => Ok(Weather::Clear),
-            Weather::Rainy => Ok(Weather::Rainy),
-            Weather::Snowy => Ok(Weather::Snowy),
-            Weather::Unknown => Ok(Weather::Unknown),
+        Ok(weather_data.cached_weather)
+    }
+}
+
+/// Gets the forecast for `at`, calling the API at most once per cooldown for the same time.
+pub async fn get_forecast(
+    provider: &dyn WeatherProvider,
+    loc: &Location,
+    at: DateTime<Utc>,
+    settings: &WeatherSettings,
+    weather_data: &mut WeatherData,
+) -> WeatherResult<Weather> {
+    let time_since_last_call = Utc::now().signed_duration_since(weather_data.last_forecast_call);
+    if let Some((forecast_time, weather)) = weather_data.cached_forecast {
+        if forecast_time == at && time_since_last_call <= Duration::minutes(settings.cooldown) {
+            return Ok(weather);
         }
     }
+
+    weather_data.last_forecast_call = Utc::now();
+
+    println!("Calling weather API for the forecast at {}", at);
+    let weather = provider.forecast(loc, at).await?;
+
+    weather_data.cached_forecast = Some((at, weather));
+
+    Ok(weather)
 }
 
-async fn call_weather_api(loc: &Location, api_key: &str) -> Result<String> {
-    let lat = loc.latitude;
-    let lon = loc.longitude;
-    let result = reqwest::get(format!(
-        "{}weather?lat={}&lon={}&appid={}",
-        API_URL, lat, lon, api_key
-    ))
-    .await?
-    .text()
-    .await?;
-    Ok(result)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn openweathermap_ids_map_to_variants() {
+        let cases = [
+            (200, Weather::Thunderstorm),
+            (232, Weather::Thunderstorm),
+            (300, Weather::Rainy),
+            (321, Weather::Rainy),
+            (500, Weather::Rainy),
+            (531, Weather::Rainy),
+            (600, Weather::Snowy),
+            (602, Weather::HeavySnow),
+            (622, Weather::HeavySnow),
+            (621, Weather::Snowy),
+            (701, Weather::Foggy),
+            (762, Weather::Foggy),
+            (771, Weather::Windy),
+            (781, Weather::Windy),
+            (800, Weather::Clear),
+            (801, Weather::Clear),
+            (802, Weather::Cloudy),
+            (804, Weather::Cloudy),
+            (900, Weather::Unknown),
+        ];
+        for (id, weather) in cases {
+            assert_eq!(Weather::from_id(id, 0.0), weather, "id {}", id);
+        }
+    }
+
+    #[test]
+    fn wmo_codes_map_to_variants() {
+        let cases = [
+            (0, Weather::Clear),
+            (1, Weather::Clear),
+            (2, Weather::Cloudy),
+            (3, Weather::Cloudy),
+            (45, Weather::Foggy),
+            (48, Weather::Foggy),
+            (51, Weather::Rainy),
+            (67, Weather::Rainy),
+            (80, Weather::Rainy),
+            (82, Weather::Rainy),
+            (71, Weather::Snowy),
+            (77, Weather::Snowy),
+            (85, Weather::Snowy),
+            (75, Weather::HeavySnow),
+            (86, Weather::HeavySnow),
+            (95, Weather::Thunderstorm),
+            (99, Weather::Thunderstorm),
+            (100, Weather::Unknown),
+        ];
+        for (code, weather) in cases {
+            assert_eq!(Weather::from_wmo_code(code, 0.0), weather, "code {}", code);
+        }
+    }
+
+    #[test]
+    fn strong_wind_makes_clear_and_cloudy_weather_windy() {
+        let below = WINDY_SPEED - 0.1;
+        assert_eq!(Weather::from_id(800, below), Weather::Clear);
+        assert_eq!(Weather::from_id(800, WINDY_SPEED), Weather::Windy);
+        assert_eq!(Weather::from_id(804, WINDY_SPEED), Weather::Windy);
+        assert_eq!(Weather::from_id(500, WINDY_SPEED), Weather::Rainy);
+        assert_eq!(Weather::from_wmo_code(3, below), Weather::Cloudy);
+        assert_eq!(Weather::from_wmo_code(0, WINDY_SPEED), Weather::Windy);
+        assert_eq!(
+            Weather::from_wmo_code(95, WINDY_SPEED),
+            Weather::Thunderstorm
+        );
+    }
+
+    #[test]
+    fn intensity_follows_the_condition() {
+        assert_eq!(Weather::intensity_from_id(500, 0.0), LIGHT);
+        assert_eq!(Weather::intensity_from_id(501, 0.0), MODERATE);
+        assert_eq!(Weather::intensity_from_id(502, 0.0), HEAVY);
+        assert_eq!(Weather::intensity_from_id(622, 0.0), HEAVY);
+        assert_eq!(Weather::intensity_from_wmo_code(61, 0.0), LIGHT);
+        assert_eq!(Weather::intensity_from_wmo_code(63, 0.0), MODERATE);
+        assert_eq!(Weather::intensity_from_wmo_code(65, 0.0), HEAVY);
+    }
+
+    #[test]
+    fn wind_intensity_grows_with_the_wind_speed() {
+        let below = WINDY_SPEED - 0.1;
+        assert_eq!(Weather::intensity_from_id(800, below), MODERATE);
+        assert_eq!(Weather::intensity_from_id(800, WINDY_SPEED), 0.5);
+        assert_eq!(Weather::intensity_from_id(804, 2.0 * WINDY_SPEED), HEAVY);
+        assert_eq!(Weather::intensity_from_id(804, 4.0 * WINDY_SPEED), HEAVY);
+        assert_eq!(Weather::intensity_from_wmo_code(0, below), MODERATE);
+        assert_eq!(Weather::intensity_from_wmo_code(3, WINDY_SPEED), 0.5);
+        assert_eq!(
+            Weather::intensity_from_wmo_code(61, 4.0 * WINDY_SPEED),
+            LIGHT
+        );
+    }
 }
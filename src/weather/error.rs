@@ -0,0 +1,49 @@
+use std::error::Error;
+use std::fmt;
+
+use reqwest::StatusCode;
+
+/// Why a weather provider could not give a report.
+#[derive(Debug)]
+pub enum WeatherError {
+    /// The request could not be sent or the response could not be read.
+    Network(reqwest::Error),
+    /// The API rejected the key.
+    Auth,
+    /// Any other unsuccessful status, e.g. 429 when rate limited.
+    Status(StatusCode),
+    /// The response did not have the expected shape.
+    Parse(String),
+}
+
+impl fmt::Display for WeatherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WeatherError::Network(e) => write!(f, "network error: {}", e),
+            WeatherError::Auth => write!(f, "the weather API key was rejected"),
+            WeatherError::Status(status) => write!(f, "weather API returned {}", status),
+            WeatherError::Parse(e) => write!(f, "unexpected weather API response: {}", e),
+        }
+    }
+}
+
+impl Error for WeatherError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WeatherError::Network(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for WeatherError {
+    fn from(e: reqwest::Error) -> Self {
+        WeatherError::Network(e)
+    }
+}
+
+impl From<serde_json::Error> for WeatherError {
+    fn from(e: serde_json::Error) -> Self {
+        WeatherError::Parse(e.to_string())
+    }
+}
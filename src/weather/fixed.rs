@@ -0,0 +1,26 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::{Location, OpenMeteo, Report, Weather, WeatherProvider, WeatherResult, MODERATE};
+
+/// Always reports the same weather. Useful without an API key and for testing. Cities are
+/// still looked up with Open-Meteo, which needs no key, so `setlocation <city>` works.
+pub struct FixedWeather(pub Weather);
+
+#[async_trait]
+impl WeatherProvider for FixedWeather {
+    async fn current(&self, _loc: &Location) -> WeatherResult<Report> {
+        Ok(Report {
+            weather: self.0,
+            intensity: MODERATE,
+        })
+    }
+
+    async fn forecast(&self, _loc: &Location, _at: DateTime<Utc>) -> WeatherResult<Weather> {
+        Ok(self.0)
+    }
+
+    async fn geocode(&self, city: &str) -> WeatherResult<Option<(String, Location)>> {
+        OpenMeteo.geocode(city).await
+    }
+}
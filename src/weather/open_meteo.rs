@@ -0,0 +1,120 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use serde::Deserialize;
+
+use super::{get_json, Location, Report, Weather, WeatherError, WeatherProvider, WeatherResult};
+
+const API_URL: &str = "https://api.open-meteo.com/v1/forecast";
+
+const GEO_URL: &str = "https://geocoding-api.open-meteo.com/v1/search";
+
+#[derive(Deserialize)]
+struct ForecastResponse {
+    current_weather: CurrentWeather,
+}
+
+#[derive(Deserialize)]
+struct CurrentWeather {
+    weathercode: u64,
+    windspeed: f64,
+}
+
+#[derive(Deserialize)]
+struct HourlyResponse {
+    hourly: Hourly,
+}
+
+/// Parallel arrays, one entry per hour.
+#[derive(Deserialize)]
+struct Hourly {
+    time: Vec<i64>,
+    weathercode: Vec<u64>,
+    windspeed_10m: Vec<f64>,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    // Left out of the response when nothing matched.
+    #[serde(default)]
+    results: Vec<Place>,
+}
+
+#[derive(Deserialize)]
+struct Place {
+    name: String,
+    latitude: f64,
+    longitude: f64,
+}
+
+/// https://open-meteo.com, free for non-commercial use and needs no API key.
+pub struct OpenMeteo;
+
+#[async_trait]
+impl WeatherProvider for OpenMeteo {
+    async fn current(&self, loc: &Location) -> WeatherResult<Report> {
+        let url = Url::parse_with_params(
+            API_URL,
+            &[
+                ("latitude", loc.latitude.to_string()),
+                ("longitude", loc.longitude.to_string()),
+                ("current_weather", String::from("true")),
+            ],
+        )
+        .expect("API_URL is a valid URL.");
+        let resp: ForecastResponse = get_json(url).await?;
+
+        let current = resp.current_weather;
+        println!(
+            "Weather code: {}, wind speed: {} km/h",
+            current.weathercode, current.windspeed
+        );
+        Ok(Report {
+            weather: Weather::from_wmo_code(current.weathercode, current.windspeed),
+            intensity: Weather::intensity_from_wmo_code(current.weathercode, current.windspeed),
+        })
+    }
+
+    async fn forecast(&self, loc: &Location, at: DateTime<Utc>) -> WeatherResult<Weather> {
+        let url = Url::parse_with_params(
+            API_URL,
+            &[
+                ("latitude", loc.latitude.to_string()),
+                ("longitude", loc.longitude.to_string()),
+                ("hourly", String::from("weathercode,windspeed_10m")),
+                ("timeformat", String::from("unixtime")),
+                ("forecast_days", String::from("2")),
+            ],
+        )
+        .expect("API_URL is a valid URL.");
+        let resp: HourlyResponse = get_json(url).await?;
+
+        let hourly = resp.hourly;
+        let (code, wind_speed) = hourly
+            .time
+            .iter()
+            .zip(hourly.weathercode.iter().zip(hourly.windspeed_10m.iter()))
+            .min_by_key(|(time, _)| (**time - at.timestamp()).abs())
+            .map(|(_, (code, wind_speed))| (*code, *wind_speed))
+            .ok_or_else(|| WeatherError::Parse(String::from("empty forecast")))?;
+
+        println!("Forecast weather code for {}: {}", at, code);
+        Ok(Weather::from_wmo_code(code, wind_speed))
+    }
+
+    async fn geocode(&self, city: &str) -> WeatherResult<Option<(String, Location)>> {
+        let url = Url::parse_with_params(GEO_URL, &[("name", city), ("count", "1")])
+            .expect("GEO_URL is a valid URL.");
+        let resp: SearchResponse = get_json(url).await?;
+
+        Ok(resp.results.into_iter().next().map(|place| {
+            (
+                place.name,
+                Location {
+                    latitude: place.latitude,
+                    longitude: place.longitude,
+                },
+            )
+        }))
+    }
+}
@@ -0,0 +1,141 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use serde::Deserialize;
+
+use super::{get_json, Location, Report, Weather, WeatherError, WeatherProvider, WeatherResult};
+
+const API_URL: &str = "https://api.openweathermap.org/data/2.5/weather";
+
+const FORECAST_URL: &str = "https://api.openweathermap.org/data/2.5/forecast";
+
+const GEO_URL: &str = "https://api.openweathermap.org/geo/1.0/direct";
+
+/// The parts of the current weather response that are used.
+#[derive(Deserialize)]
+struct CurrentResponse {
+    weather: Vec<Condition>,
+    #[serde(default)]
+    wind: Wind,
+}
+
+/// The 5 day forecast in 3 hour steps.
+#[derive(Deserialize)]
+struct ForecastResponse {
+    list: Vec<ForecastStep>,
+}
+
+#[derive(Deserialize)]
+struct ForecastStep {
+    dt: i64,
+    weather: Vec<Condition>,
+    #[serde(default)]
+    wind: Wind,
+}
+
+#[derive(Deserialize)]
+struct Condition {
+    id: u32,
+}
+
+/// Calm if the response has no wind.
+#[derive(Deserialize, Default)]
+struct Wind {
+    /// In m/s, the API's default units.
+    speed: f64,
+}
+
+impl Wind {
+    fn km_per_hour(&self) -> f64 {
+        self.speed * 3.6
+    }
+}
+
+#[derive(Deserialize)]
+struct Place {
+    name: String,
+    lat: f64,
+    lon: f64,
+}
+
+/// https://openweathermap.org, needs an API key.
+pub struct OpenWeatherMap {
+    api_key: String,
+}
+
+impl OpenWeatherMap {
+    pub fn new(api_key: String) -> Self {
+        OpenWeatherMap { api_key }
+    }
+
+    fn url(&self, base: &str, loc: &Location) -> Url {
+        Url::parse_with_params(
+            base,
+            &[
+                ("lat", loc.latitude.to_string()),
+                ("lon", loc.longitude.to_string()),
+                ("appid", self.api_key.clone()),
+            ],
+        )
+        .expect("API URLs are valid.")
+    }
+}
+
+fn first_condition(conditions: &[Condition]) -> WeatherResult<u32> {
+    conditions
+        .first()
+        .map(|condition| condition.id)
+        .ok_or_else(|| WeatherError::Parse(String::from("no weather conditions")))
+}
+
+#[async_trait]
+impl WeatherProvider for OpenWeatherMap {
+    async fn current(&self, loc: &Location) -> WeatherResult<Report> {
+        let resp: CurrentResponse = get_json(self.url(API_URL, loc)).await?;
+
+        let weather_id = first_condition(&resp.weather)?;
+        let wind_speed = resp.wind.km_per_hour();
+
+        println!(
+            "Weather_ID: {}, wind speed: {} km/h",
+            weather_id, wind_speed
+        );
+        Ok(Report {
+            weather: Weather::from_id(weather_id, wind_speed),
+            intensity: Weather::intensity_from_id(weather_id, wind_speed),
+        })
+    }
+
+    async fn forecast(&self, loc: &Location, at: DateTime<Utc>) -> WeatherResult<Weather> {
+        let resp: ForecastResponse = get_json(self.url(FORECAST_URL, loc)).await?;
+
+        let step = resp
+            .list
+            .iter()
+            .min_by_key(|step| (step.dt - at.timestamp()).abs())
+            .ok_or_else(|| WeatherError::Parse(String::from("empty forecast")))?;
+        let weather_id = first_condition(&step.weather)?;
+
+        println!("Forecast Weather_ID for {}: {}", at, weather_id);
+        Ok(Weather::from_id(weather_id, step.wind.km_per_hour()))
+    }
+
+    async fn geocode(&self, city: &str) -> WeatherResult<Option<(String, Location)>> {
+        let url = Url::parse_with_params(
+            GEO_URL,
+            &[("q", city), ("limit", "1"), ("appid", &self.api_key)],
+        )
+        .expect("GEO_URL is a valid URL.");
+        let places: Vec<Place> = get_json(url).await?;
+
+        Ok(places.into_iter().next().map(|place| {
+            (
+                place.name,
+                Location {
+                    latitude: place.lat,
+                    longitude: place.lon,
+                },
+            )
+        }))
+    }
+}